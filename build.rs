@@ -0,0 +1,31 @@
+// SPDX-FileCopyrightText: 2019 Tuomas Siipola
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+// Lists every map in data/maps for the embed-assets feature, so that new maps
+// are built into the executable without having to name them anywhere.
+fn main() {
+    println!("cargo:rerun-if-changed=data/maps");
+
+    let mut names: Vec<String> = fs::read_dir("data/maps")
+        .expect("data/maps")
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.ends_with(".map"))
+        .collect();
+    names.sort();
+
+    let mut list = String::from("&[\n");
+    for name in names {
+        list.push_str(&format!(
+            "    (\"maps/{0}\", include_bytes!(concat!(env!(\"CARGO_MANIFEST_DIR\"), \"/data/maps/{0}\")) as &[u8]),\n",
+            name
+        ));
+    }
+    list.push(']');
+
+    let out_dir = env::var_os("OUT_DIR").unwrap();
+    fs::write(Path::new(&out_dir).join("embedded_maps.rs"), list).unwrap();
+}
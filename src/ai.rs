@@ -0,0 +1,221 @@
+// SPDX-FileCopyrightText: 2019 Tuomas Siipola
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::collections::VecDeque;
+
+use crate::direction::Direction;
+use crate::map::Map;
+use crate::snake::Snake;
+use crate::tile::Tile;
+
+const DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Right,
+    Direction::Down,
+    Direction::Left,
+];
+
+// Decides where a computer controlled snake goes next. Called once per tick
+// with the current state of the map.
+pub trait SnakeController {
+    fn next_direction(&mut self, map: &Map, snake: &Snake) -> Direction;
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+        }
+    }
+
+    pub fn controller(self) -> Box<dyn SnakeController> {
+        match self {
+            Difficulty::Easy => Box::new(Greedy),
+            Difficulty::Normal => Box::new(FloodFill { avoid_heads: false }),
+            Difficulty::Hard => Box::new(FloodFill { avoid_heads: true }),
+        }
+    }
+}
+
+pub const BOTS: [&str; 3] = ["greedy", "flood-fill", "cautious"];
+
+pub fn bot(name: &str) -> Option<Box<dyn SnakeController>> {
+    match name {
+        "greedy" => Some(Box::new(Greedy)),
+        "flood-fill" => Some(Box::new(FloodFill { avoid_heads: false })),
+        "cautious" => Some(Box::new(FloodFill { avoid_heads: true })),
+        _ => None,
+    }
+}
+
+fn is_free(tile: Tile) -> bool {
+    matches!(tile, Tile::Empty | Tile::Food(_))
+}
+
+// Directions that don't reverse the snake or run straight into something.
+fn safe_moves(map: &Map, snake: &Snake) -> Vec<(Direction, (usize, usize))> {
+    let head = (snake.head.x as usize, snake.head.y as usize);
+    DIRECTIONS
+        .iter()
+        .filter(|direction| **direction != snake.head.direction.opposite())
+        .filter_map(|direction| {
+            map.step(head.0, head.1, *direction)
+                .map(|position| (*direction, position))
+        })
+        .filter(|(_, (x, y))| is_free(map.tile(*x, *y)))
+        .collect()
+}
+
+// Distance along the axis, going around the edge if the map wraps.
+fn axis_distance(map: &Map, a: usize, b: usize, size: usize) -> usize {
+    let d = a.abs_diff(b);
+    if map.wrap {
+        d.min(size - d)
+    } else {
+        d
+    }
+}
+
+fn food_positions(map: &Map) -> Vec<(usize, usize)> {
+    (0..map.width)
+        .flat_map(|x| (0..map.height).map(move |y| (x, y)))
+        .filter(|(x, y)| map.tile(*x, *y).is_food())
+        .collect()
+}
+
+// Heads straight for the closest food and only looks one tile ahead, so it
+// easily traps itself.
+pub struct Greedy;
+
+impl SnakeController for Greedy {
+    fn next_direction(&mut self, map: &Map, snake: &Snake) -> Direction {
+        let food = food_positions(map);
+        let distance = |(x, y): (usize, usize)| {
+            food.iter()
+                .map(|(fx, fy)| {
+                    axis_distance(map, x, *fx, map.width) + axis_distance(map, y, *fy, map.height)
+                })
+                .min()
+                .unwrap_or(0)
+        };
+        safe_moves(map, snake)
+            .into_iter()
+            .min_by_key(|(direction, position)| {
+                (distance(*position), *direction != snake.head.direction)
+            })
+            .map_or(snake.head.direction, |(direction, _)| direction)
+    }
+}
+
+// Follows the shortest path to food, but only into areas large enough to fit
+// the snake. With avoid_heads it also keeps away from tiles other snakes
+// could move into.
+pub struct FloodFill {
+    pub avoid_heads: bool,
+}
+
+struct Search {
+    reachable: usize,
+    food_distance: Option<usize>,
+}
+
+// Breadth-first search from a tile the snake is about to move to.
+fn search(map: &Map, start: (usize, usize)) -> Search {
+    let mut visited = vec![false; map.width * map.height];
+    let mut queue = VecDeque::new();
+    let mut result = Search {
+        reachable: 0,
+        food_distance: None,
+    };
+    visited[start.0 * map.height + start.1] = true;
+    queue.push_back((start, 0));
+    while let Some((position, distance)) = queue.pop_front() {
+        result.reachable += 1;
+        if result.food_distance.is_none() && map.tile(position.0, position.1).is_food() {
+            result.food_distance = Some(distance);
+        }
+        for direction in DIRECTIONS.iter() {
+            let (x, y) = match map.step(position.0, position.1, *direction) {
+                Some(position) => position,
+                None => continue,
+            };
+            if !visited[x * map.height + y] && is_free(map.tile(x, y)) {
+                visited[x * map.height + y] = true;
+                queue.push_back(((x, y), distance + 1));
+            }
+        }
+    }
+    result
+}
+
+fn snake_length(map: &Map, owner: Option<u8>) -> usize {
+    map.tiles().filter(|tile| tile.owner() == owner).count()
+}
+
+// Tiles that the heads of other snakes can move into on the next tick.
+fn contested(map: &Map, owner: Option<u8>) -> Vec<(usize, usize)> {
+    (0..map.width)
+        .flat_map(|x| (0..map.height).map(move |y| (x, y)))
+        .filter(|(x, y)| match map.tile(*x, *y) {
+            Tile::SnakeHead(other, _) => Some(other) != owner,
+            _ => false,
+        })
+        .flat_map(|head| DIRECTIONS.iter().map(move |direction| (head, *direction)))
+        .filter_map(|((x, y), direction)| map.step(x, y, direction))
+        .collect()
+}
+
+impl SnakeController for FloodFill {
+    fn next_direction(&mut self, map: &Map, snake: &Snake) -> Direction {
+        let owner = map
+            .tile(snake.head.x as usize, snake.head.y as usize)
+            .owner();
+        let mut moves = safe_moves(map, snake);
+        if self.avoid_heads {
+            let contested = contested(map, owner);
+            let uncontested: Vec<_> = moves
+                .iter()
+                .cloned()
+                .filter(|(_, position)| !contested.contains(position))
+                .collect();
+            if !uncontested.is_empty() {
+                moves = uncontested;
+            }
+        }
+
+        let length = snake_length(map, owner);
+        let searches: Vec<_> = moves
+            .into_iter()
+            .map(|(direction, position)| (direction, search(map, position)))
+            .collect();
+
+        let roomy = searches
+            .iter()
+            .filter(|(_, search)| search.reachable >= length)
+            .min_by_key(|(direction, search)| {
+                (
+                    search.food_distance.unwrap_or(usize::MAX),
+                    *direction != snake.head.direction,
+                )
+            });
+        if let Some((direction, _)) = roomy {
+            return *direction;
+        }
+
+        // Every move leads somewhere too small, so stay alive for as long as
+        // possible.
+        searches
+            .iter()
+            .max_by_key(|(_, search)| search.reachable)
+            .map_or(snake.head.direction, |(direction, _)| *direction)
+    }
+}
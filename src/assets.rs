@@ -0,0 +1,162 @@
+// SPDX-FileCopyrightText: 2019 Tuomas Siipola
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use sdl2::render::{Texture, TextureCreator};
+use sdl2::rwops::RWops;
+use sdl2::surface::Surface;
+use sdl2::video::WindowContext;
+
+use crate::font::Font;
+use crate::map::Map;
+use crate::paths;
+
+#[cfg(feature = "embed-assets")]
+macro_rules! embed {
+    ($($name:expr),* $(,)?) => {
+        &[$(($name, include_bytes!(concat!("../data/", $name)) as &[u8])),*]
+    };
+}
+
+// Copies of the default data built into the executable. The maps are listed
+// by build.rs from data/maps.
+#[cfg(feature = "embed-assets")]
+const EMBEDDED: &[(&str, &[u8])] = embed![
+    "images/tiles.bmp",
+    "images/logo.bmp",
+    "images/NeoSans.bmp",
+    "campaign",
+];
+
+#[cfg(feature = "embed-assets")]
+const EMBEDDED_MAPS: &[(&str, &[u8])] = include!(concat!(env!("OUT_DIR"), "/embedded_maps.rs"));
+
+#[cfg(not(feature = "embed-assets"))]
+const EMBEDDED: &[(&str, &[u8])] = &[];
+
+#[cfg(not(feature = "embed-assets"))]
+const EMBEDDED_MAPS: &[(&str, &[u8])] = &[];
+
+fn embedded() -> impl Iterator<Item = &'static (&'static str, &'static [u8])> {
+    EMBEDDED.iter().chain(EMBEDDED_MAPS)
+}
+
+pub enum AssetError {
+    NotFound(String, Vec<PathBuf>),
+    Invalid(PathBuf, String),
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssetError::NotFound(name, searched) => {
+                write!(f, "{} not found, searched:", name)?;
+                for dir in searched {
+                    write!(f, "\n  {}", dir.display())?;
+                }
+                Ok(())
+            }
+            AssetError::Invalid(path, message) => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+// Finds a file like "images/tiles.bmp" from the first data directory that
+// has it.
+pub fn find(name: &str) -> Result<PathBuf, AssetError> {
+    let dirs = paths::asset_dirs();
+    dirs.iter()
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+        .ok_or_else(|| AssetError::NotFound(name.to_string(), dirs))
+}
+
+// Every data directory may add files to a directory like "maps".
+pub fn find_dirs(name: &str) -> Vec<PathBuf> {
+    paths::asset_dirs()
+        .into_iter()
+        .map(|dir| dir.join(name))
+        .filter(|path| path.is_dir())
+        .collect()
+}
+
+// Files built into the executable in a directory like "maps", by file name.
+pub fn embedded_files(dir: &str) -> Vec<(&'static str, &'static [u8])> {
+    embedded()
+        .filter_map(|(name, bytes)| {
+            let file_name = name.strip_prefix(dir)?.strip_prefix('/')?;
+            Some((file_name, *bytes))
+        })
+        .collect()
+}
+
+// Reads a file from the data directories, falling back to the copy built into
+// the executable. Also returns the path to use in messages.
+pub fn read(name: &str) -> Result<(PathBuf, Cow<'static, [u8]>), AssetError> {
+    match find(name) {
+        Ok(path) => match fs::read(&path) {
+            Ok(bytes) => Ok((path, Cow::Owned(bytes))),
+            Err(err) => Err(AssetError::Invalid(path, err.to_string())),
+        },
+        Err(err) => embedded()
+            .find(|(embedded, _)| *embedded == name)
+            .map(|(_, bytes)| (PathBuf::from(name), Cow::Borrowed(*bytes)))
+            .ok_or(err),
+    }
+}
+
+pub fn load_map(name: &str) -> Result<Map, AssetError> {
+    let (path, bytes) = read(name)?;
+    Map::parse(&bytes).map_err(|err| AssetError::Invalid(path, err.to_string()))
+}
+
+fn load_surface(name: &str) -> Result<(PathBuf, Surface<'static>), AssetError> {
+    let (path, bytes) = read(name)?;
+    let surface = RWops::from_bytes(&bytes)
+        .and_then(|mut rwops| Surface::load_bmp_rw(&mut rwops))
+        .map_err(|err| AssetError::Invalid(path.clone(), err))?;
+    Ok((path, surface))
+}
+
+// Textures are loaded once and shared after that.
+pub struct Assets<'a> {
+    texture_creator: &'a TextureCreator<WindowContext>,
+    textures: HashMap<String, Rc<Texture<'a>>>,
+}
+
+impl<'a> Assets<'a> {
+    pub fn new(texture_creator: &'a TextureCreator<WindowContext>) -> Assets<'a> {
+        Assets {
+            texture_creator,
+            textures: HashMap::new(),
+        }
+    }
+
+    pub fn texture(&mut self, name: &str) -> Result<Rc<Texture<'a>>, AssetError> {
+        if let Some(texture) = self.textures.get(name) {
+            return Ok(texture.clone());
+        }
+        let (path, surface) = load_surface(name)?;
+        let texture = self
+            .texture_creator
+            .create_texture_from_surface(surface)
+            .map_err(|err| AssetError::Invalid(path, err.to_string()))?;
+        let texture = Rc::new(texture);
+        self.textures.insert(name.to_string(), texture.clone());
+        Ok(texture)
+    }
+
+    // Fonts change the color of their texture when drawing, so each one gets
+    // a texture of its own.
+    pub fn font(&self, name: &str) -> Result<Font<'a>, AssetError> {
+        let (path, surface) = load_surface(name)?;
+        Font::from_surface(self.texture_creator, surface)
+            .map_err(|err| AssetError::Invalid(path, err))
+    }
+}
@@ -0,0 +1,319 @@
+// SPDX-FileCopyrightText: 2019 Tuomas Siipola
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::io::{self, BufReader};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use sdl2::audio::{
+    AudioCVT, AudioCallback, AudioDevice, AudioFormat, AudioSpecDesired, AudioSpecWAV,
+};
+use sdl2::rwops::RWops;
+use sdl2::Sdl;
+
+use crate::assets::{self, AssetError};
+use crate::map::Map;
+use crate::paths;
+use crate::simulation::GameEvent;
+
+const FREQUENCY: i32 = 22050;
+const MAX_VOICES: usize = 8;
+pub const MAX_VOLUME: u8 = 10;
+const DEFAULT_VOLUME: u8 = 7;
+const DEFAULT_MUSIC: &str = "theme.wav";
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Sound {
+    Eat,
+    Turn,
+    Die,
+    Navigate,
+    Start,
+}
+
+const SOUNDS: [Sound; 5] = [
+    Sound::Eat,
+    Sound::Turn,
+    Sound::Die,
+    Sound::Navigate,
+    Sound::Start,
+];
+
+impl Sound {
+    fn file_name(self) -> &'static str {
+        match self {
+            Sound::Eat => "eat.wav",
+            Sound::Turn => "turn.wav",
+            Sound::Die => "die.wav",
+            Sound::Navigate => "navigate.wav",
+            Sound::Start => "start.wav",
+        }
+    }
+}
+
+struct Voice {
+    samples: Arc<Vec<i16>>,
+    position: usize,
+}
+
+// Runs on SDL's audio thread and mixes the music with any playing effects.
+struct Mixer {
+    music: Option<Voice>,
+    voices: Vec<Voice>,
+    gain: f32,
+}
+
+impl AudioCallback for Mixer {
+    type Channel = i16;
+
+    fn callback(&mut self, out: &mut [i16]) {
+        for sample in out.iter_mut() {
+            let mut sum = 0;
+            if let Some(music) = &mut self.music {
+                sum += i32::from(music.samples[music.position]);
+                music.position = (music.position + 1) % music.samples.len();
+            }
+            for voice in &mut self.voices {
+                if let Some(value) = voice.samples.get(voice.position) {
+                    sum += i32::from(*value);
+                    voice.position += 1;
+                }
+            }
+            let value = (sum as f32 * self.gain) as i32;
+            *sample = value.max(i32::from(i16::MIN)).min(i32::from(i16::MAX)) as i16;
+        }
+        self.voices
+            .retain(|voice| voice.position < voice.samples.len());
+    }
+}
+
+// Converts a WAV file to the format of the output device.
+fn load_wav(name: &str) -> Result<Arc<Vec<i16>>, AssetError> {
+    let (path, bytes) = assets::read(name)?;
+    let invalid = |err| AssetError::Invalid(path.clone(), err);
+    let wav = RWops::from_bytes(&bytes)
+        .and_then(|mut rwops| AudioSpecWAV::load_wav_rw(&mut rwops))
+        .map_err(invalid)?;
+    let cvt = AudioCVT::new(
+        wav.format,
+        wav.channels,
+        wav.freq,
+        AudioFormat::s16_sys(),
+        1,
+        FREQUENCY,
+    )
+    .map_err(invalid)?;
+    let bytes = cvt.convert(wav.buffer().to_vec());
+    let samples: Vec<i16> = bytes
+        .chunks_exact(2)
+        .map(|bytes| i16::from_ne_bytes([bytes[0], bytes[1]]))
+        .collect();
+    if samples.is_empty() {
+        return Err(invalid("no samples".to_string()));
+    }
+    Ok(Arc::new(samples))
+}
+
+pub fn map_music(map: &Map) -> &str {
+    map.music.as_deref().unwrap_or(DEFAULT_MUSIC)
+}
+
+fn settings_path() -> Option<PathBuf> {
+    paths::config_dir().map(|path| path.join("audio"))
+}
+
+// Everything is optional: without an audio device or sound files the game
+// just stays quiet.
+pub struct Audio {
+    device: Option<AudioDevice<Mixer>>,
+    sounds: Vec<(Sound, Arc<Vec<i16>>)>,
+    music: Option<String>,
+    volume: u8,
+    muted: bool,
+}
+
+impl Audio {
+    pub fn new(sdl_context: &Sdl) -> Audio {
+        let mut audio = Audio {
+            device: None,
+            sounds: Vec::new(),
+            music: None,
+            volume: DEFAULT_VOLUME,
+            muted: false,
+        };
+        audio.load_settings();
+        let desired = AudioSpecDesired {
+            freq: Some(FREQUENCY),
+            channels: Some(1),
+            samples: Some(512),
+        };
+        let gain = audio.gain();
+        let device = sdl_context.audio().and_then(|subsystem| {
+            subsystem.open_playback(None, &desired, |_| Mixer {
+                music: None,
+                voices: Vec::new(),
+                gain,
+            })
+        });
+        match device {
+            Ok(device) => {
+                device.resume();
+                audio.device = Some(device);
+            }
+            Err(err) => {
+                eprintln!("audio disabled: {}", err);
+                return audio;
+            }
+        }
+        for sound in SOUNDS.iter() {
+            match load_wav(&format!("sounds/{}", sound.file_name())) {
+                Ok(samples) => audio.sounds.push((*sound, samples)),
+                Err(err) => eprintln!("{}", err),
+            }
+        }
+        audio
+    }
+
+    fn load_settings(&mut self) {
+        let path = match settings_path() {
+            Some(path) => path,
+            None => return,
+        };
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return,
+            Err(err) => {
+                eprintln!("{}: {}", path.display(), err);
+                return;
+            }
+        };
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => {
+                    eprintln!("{}: {}", path.display(), err);
+                    break;
+                }
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, '=').map(str::trim);
+            match (parts.next(), parts.next()) {
+                (Some("volume"), Some(value)) => match value.parse() {
+                    Ok(volume) if volume <= MAX_VOLUME => self.volume = volume,
+                    _ => eprintln!("{}:{}: invalid volume, ignoring", path.display(), i + 1),
+                },
+                (Some("muted"), Some(value)) => match value {
+                    "true" => self.muted = true,
+                    "false" => self.muted = false,
+                    _ => eprintln!("{}:{}: invalid setting, ignoring", path.display(), i + 1),
+                },
+                _ => eprintln!("{}:{}: invalid setting, ignoring", path.display(), i + 1),
+            }
+        }
+    }
+
+    fn save_settings(&self) {
+        let result = settings_path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))
+            .and_then(|path| {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                let mut file = File::create(path)?;
+                writeln!(file, "volume = {}", self.volume)?;
+                writeln!(file, "muted = {}", self.muted)
+            });
+        if let Err(err) = result {
+            eprintln!("failed to save audio settings: {}", err);
+        }
+    }
+
+    fn gain(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            f32::from(self.volume) / f32::from(MAX_VOLUME)
+        }
+    }
+
+    fn update_gain(&mut self) {
+        let gain = self.gain();
+        if let Some(device) = &mut self.device {
+            device.lock().gain = gain;
+        }
+        self.save_settings();
+    }
+
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    pub fn set_volume(&mut self, volume: u8) {
+        self.volume = volume.min(MAX_VOLUME);
+        self.update_gain();
+    }
+
+    pub fn muted(&self) -> bool {
+        self.muted
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+        self.update_gain();
+    }
+
+    pub fn play(&mut self, sound: Sound) {
+        let samples = match self.sounds.iter().find(|(s, _)| *s == sound) {
+            Some((_, samples)) => samples.clone(),
+            None => return,
+        };
+        if let Some(device) = &mut self.device {
+            let mut mixer = device.lock();
+            if mixer.voices.len() >= MAX_VOICES {
+                mixer.voices.remove(0);
+            }
+            mixer.voices.push(Voice {
+                samples,
+                position: 0,
+            });
+        }
+    }
+
+    pub fn play_events(&mut self, events: &[GameEvent]) {
+        for event in events {
+            match event {
+                GameEvent::FoodEaten(..) => self.play(Sound::Eat),
+                GameEvent::Died(_) => self.play(Sound::Die),
+            }
+        }
+    }
+
+    // Switches to a track in the music directory, or stops the music if there is none.
+    // The track keeps playing if it is already on.
+    pub fn play_music(&mut self, name: Option<&str>) {
+        if self.music.as_deref() == name {
+            return;
+        }
+        self.music = name.map(str::to_string);
+        let device = match &mut self.device {
+            Some(device) => device,
+            None => return,
+        };
+        let music = name.and_then(|name| match load_wav(&format!("music/{}", name)) {
+            Ok(samples) => Some(Voice {
+                samples,
+                position: 0,
+            }),
+            Err(err) => {
+                eprintln!("{}", err);
+                None
+            }
+        });
+        device.lock().music = music;
+    }
+}
@@ -0,0 +1,159 @@
+// SPDX-FileCopyrightText: 2019 Tuomas Siipola
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::str::FromStr;
+
+use crate::ai::{self, SnakeController};
+use crate::map::Map;
+use crate::simulation::{random_seed, DeathCause, Simulation};
+
+pub const USAGE: &str =
+    "natrix bench --map <file> --bot <name> [--games <n>] [--seed <seed>] [--max-ticks <n>]";
+
+const DEATH_CAUSES: [(DeathCause, &str); 4] = [
+    (DeathCause::Wall, "wall"),
+    (DeathCause::OwnBody, "own body"),
+    (DeathCause::OtherSnake, "other snake"),
+    (DeathCause::HeadOn, "head-on"),
+];
+
+pub struct Options {
+    map: String,
+    bot: String,
+    games: u32,
+    seed: u32,
+    max_ticks: u32,
+}
+
+struct GameResult {
+    score: u32,
+    ticks: u32,
+    death: Option<DeathCause>,
+}
+
+fn parse_value<T: FromStr>(name: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} requires a value", name))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", name, value))
+}
+
+pub fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut map = None;
+    let mut bot = None;
+    let mut options = Options {
+        map: String::new(),
+        bot: String::new(),
+        games: 100,
+        seed: random_seed(),
+        max_ticks: 10_000,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--map" => map = Some(parse_value::<String>("--map", args.next())?),
+            "--bot" => bot = Some(parse_value::<String>("--bot", args.next())?),
+            "--games" => options.games = parse_value("--games", args.next())?,
+            "--seed" => options.seed = parse_value("--seed", args.next())?,
+            "--max-ticks" => options.max_ticks = parse_value("--max-ticks", args.next())?,
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+    if options.games == 0 {
+        return Err("--games must be at least 1".to_string());
+    }
+    options.map = map.ok_or("--map is required")?;
+    options.bot = bot.ok_or("--bot is required")?;
+    if ai::bot(&options.bot).is_none() {
+        return Err(format!(
+            "unknown bot: {} (available: {})",
+            options.bot,
+            ai::BOTS.join(", ")
+        ));
+    }
+    Ok(options)
+}
+
+// Plays one game with the same rules as the real game. Games that are still
+// going after max_ticks are stopped so that a bot that circles forever can't
+// hang the benchmark.
+fn play(map: &Map, controller: &mut dyn SnakeController, seed: u32, max_ticks: u32) -> GameResult {
+    let mut simulation = Simulation::new(map, seed, 1);
+    let mut ticks = 0;
+    while simulation.players[0].alive && ticks < max_ticks {
+        let direction = controller.next_direction(&simulation.map, &simulation.players[0].snake);
+        simulation.step(&[direction]);
+        ticks += 1;
+    }
+    GameResult {
+        score: simulation.players[0].score,
+        ticks,
+        death: simulation.players[0].death,
+    }
+}
+
+fn mean(values: &[u32]) -> f64 {
+    values.iter().map(|value| f64::from(*value)).sum::<f64>() / values.len() as f64
+}
+
+fn median(values: &[u32]) -> f64 {
+    let mut values = values.to_vec();
+    values.sort_unstable();
+    let middle = values.len() / 2;
+    if values.len() % 2 == 1 {
+        f64::from(values[middle])
+    } else {
+        (f64::from(values[middle - 1]) + f64::from(values[middle])) / 2.0
+    }
+}
+
+fn print_stats(name: &str, values: &[u32]) {
+    println!(
+        "{}: mean {:.1}, median {:.1}, max {}",
+        name,
+        mean(values),
+        median(values),
+        values.iter().max().unwrap()
+    );
+}
+
+pub fn run(options: &Options) -> Result<(), String> {
+    let map = Map::load(&options.map).map_err(|err| format!("{}: {}", options.map, err))?;
+    let mut controller = ai::bot(&options.bot).unwrap();
+
+    let results: Vec<GameResult> = (0..options.games)
+        .map(|i| {
+            play(
+                &map,
+                controller.as_mut(),
+                options.seed.wrapping_add(i),
+                options.max_ticks,
+            )
+        })
+        .collect();
+
+    let scores: Vec<u32> = results.iter().map(|result| result.score).collect();
+    let ticks: Vec<u32> = results.iter().map(|result| result.ticks).collect();
+
+    println!("map: {}", map.name);
+    println!("bot: {}", options.bot);
+    println!("games: {} (seeds {}..)", options.games, options.seed);
+    print_stats("score", &scores);
+    print_stats("ticks", &ticks);
+    let mut causes: Vec<String> = DEATH_CAUSES
+        .iter()
+        .map(|(cause, name)| {
+            let count = results
+                .iter()
+                .filter(|result| result.death == Some(*cause))
+                .count();
+            format!("{} {}", name, count)
+        })
+        .collect();
+    let survived = results
+        .iter()
+        .filter(|result| result.death.is_none())
+        .count();
+    causes.push(format!("still alive {}", survived));
+    println!("deaths: {}", causes.join(", "));
+    Ok(())
+}
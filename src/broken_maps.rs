@@ -0,0 +1,131 @@
+// SPDX-FileCopyrightText: 2019 Tuomas Siipola
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use sdl2::event::{Event, EventPollIterator};
+use sdl2::pixels::Color;
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::Window;
+
+use crate::audio::Audio;
+use crate::font::Font;
+use crate::input::{Bindings, InputAction};
+use crate::map::MapError;
+use crate::state::{Action, State};
+
+const TEXT_WIDTH: u32 = 300;
+
+// A map file that failed to load and why.
+#[derive(Clone)]
+pub struct BrokenMap {
+    pub file: String,
+    pub errors: Vec<String>,
+}
+
+impl BrokenMap {
+    pub fn new(file: &str, err: &MapError) -> BrokenMap {
+        let errors = match err {
+            MapError::Io(err) => vec![err.to_string()],
+            MapError::Invalid(_) => err.errors().iter().map(|error| error.to_string()).collect(),
+        };
+        BrokenMap {
+            file: file.to_string(),
+            errors,
+        }
+    }
+}
+
+// Lists maps that couldn't be loaded so that map authors can see what is
+// wrong with them. Up and Down scroll one map at a time.
+pub struct BrokenMaps {
+    maps: Vec<BrokenMap>,
+    first: usize,
+}
+
+impl BrokenMaps {
+    pub fn new(maps: Vec<BrokenMap>) -> BrokenMaps {
+        BrokenMaps { maps, first: 0 }
+    }
+}
+
+// Splits text into lines that fit in the given width.
+fn wrap(font: &Font, text: &str, width: u32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split(' ') {
+        let candidate = if line.is_empty() {
+            word.to_string()
+        } else {
+            format!("{} {}", line, word)
+        };
+        if font.measure(&candidate) > width && !line.is_empty() {
+            lines.push(line);
+            line = word.to_string();
+        } else {
+            line = candidate;
+        }
+    }
+    lines.push(line);
+    lines
+}
+
+impl State for BrokenMaps {
+    fn handle_events(
+        &mut self,
+        events: EventPollIterator,
+        bindings: &mut Bindings,
+        _audio: &mut Audio,
+    ) -> Action {
+        for event in events {
+            match event {
+                Event::Quit { .. } => return Action::Quit,
+                Event::KeyDown {
+                    scancode: Some(scancode),
+                    ..
+                } => match bindings.action(scancode) {
+                    Some(InputAction::Up) => self.first = self.first.saturating_sub(1),
+                    Some(InputAction::Down) if self.first + 1 < self.maps.len() => self.first += 1,
+                    Some(InputAction::Back) => return Action::Pop(None),
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        Action::None
+    }
+
+    fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        font: &mut Font,
+        _tiles: &Texture,
+        _logo: &Texture,
+        _bindings: &Bindings,
+    ) {
+        canvas.set_draw_color(Color::RGB(215, 227, 244));
+        canvas.clear();
+
+        font.draw(
+            canvas,
+            ((320 - font.measure("Broken maps")) / 2) as i32,
+            20,
+            "Broken maps",
+            Color::RGB(0, 0, 0),
+        );
+
+        let mut y = 40;
+        for map in &self.maps[self.first..] {
+            if y > 230 {
+                break;
+            }
+            font.draw(canvas, 10, y, &map.file, Color::RGB(0, 0, 0));
+            y += 10;
+            for error in &map.errors {
+                for line in wrap(font, error, TEXT_WIDTH - 10) {
+                    font.draw(canvas, 20, y, &line, Color::RGB(120, 0, 0));
+                    y += 10;
+                }
+            }
+            y += 5;
+        }
+    }
+}
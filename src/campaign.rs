@@ -0,0 +1,194 @@
+// SPDX-FileCopyrightText: 2019 Tuomas Siipola
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::assets;
+use crate::map::Map;
+use crate::paths;
+
+const HEADER: &str = "natrix-campaign 1";
+const PROGRESS_HEADER: &str = "natrix-campaign-progress 1";
+
+#[derive(Copy, Clone)]
+pub enum Goal {
+    // Pieces of food to eat.
+    Food(u32),
+    // Seconds of game time to stay alive.
+    Survive(u32),
+}
+
+impl Goal {
+    fn parse(kind: &str, amount: &str) -> Option<Goal> {
+        let amount = amount.parse().ok().filter(|amount| *amount > 0)?;
+        match kind {
+            "food" => Some(Goal::Food(amount)),
+            "survive" => Some(Goal::Survive(amount)),
+            _ => None,
+        }
+    }
+
+    pub fn describe(self) -> String {
+        match self {
+            Goal::Food(amount) => format!("Eat {} food", amount),
+            Goal::Survive(seconds) => format!("Survive {} seconds", seconds),
+        }
+    }
+
+    pub fn progress(self, eaten: u32, time: Duration) -> String {
+        match self {
+            Goal::Food(amount) => format!("Food: {}/{}", eaten.min(amount), amount),
+            Goal::Survive(seconds) => {
+                format!("Time: {}/{}", (time.as_secs() as u32).min(seconds), seconds)
+            }
+        }
+    }
+
+    pub fn reached(self, eaten: u32, time: Duration) -> bool {
+        match self {
+            Goal::Food(amount) => eaten >= amount,
+            Goal::Survive(seconds) => time >= Duration::from_secs(seconds.into()),
+        }
+    }
+}
+
+// A map that failed to load is kept in the list so that the numbering of the
+// campaign doesn't change, but it can't be played.
+#[derive(Clone)]
+pub struct Level {
+    pub file: String,
+    pub goal: Goal,
+    pub map: Option<Map>,
+}
+
+fn parse_line(line: &str) -> Option<(String, Goal)> {
+    let mut parts = line.split_whitespace();
+    let file = parts.next()?.to_string();
+    let goal = Goal::parse(parts.next()?, parts.next()?)?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((file, goal))
+}
+
+// The campaign lists maps in the maps directory one per line followed by their goal,
+// e.g. "basic.map food 10" or "rooms.map survive 60".
+pub fn parse(path: &Path, bytes: &[u8]) -> io::Result<Vec<Level>> {
+    let mut lines = bytes.lines();
+    match lines.next() {
+        Some(Ok(ref header)) if header == HEADER => {}
+        Some(Err(err)) => return Err(err),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a campaign file",
+            ))
+        }
+    }
+    let mut levels = Vec::new();
+    for (i, line) in lines.enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (file, goal) = match parse_line(line) {
+            Some(level) => level,
+            None => {
+                eprintln!("{}:{}: invalid level, ignoring", path.display(), i + 2);
+                continue;
+            }
+        };
+        let map = match assets::load_map(&format!("maps/{}", file)) {
+            Ok(map) => Some(map),
+            Err(err) => {
+                eprintln!("{}", err);
+                None
+            }
+        };
+        levels.push(Level { file, goal, map });
+    }
+    Ok(levels)
+}
+
+// Completed levels are remembered by the file name of their map.
+pub struct Progress {
+    completed: HashSet<String>,
+}
+
+fn progress_path() -> Option<PathBuf> {
+    paths::data_dir().map(|path| path.join("campaign-progress"))
+}
+
+impl Progress {
+    pub fn load() -> Progress {
+        let mut progress = Progress {
+            completed: HashSet::new(),
+        };
+        let path = match progress_path() {
+            Some(path) => path,
+            None => return progress,
+        };
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return progress,
+            Err(err) => {
+                eprintln!("{}: {}", path.display(), err);
+                return progress;
+            }
+        };
+        let mut lines = BufReader::new(file).lines();
+        match lines.next() {
+            Some(Ok(ref header)) if header == PROGRESS_HEADER => {}
+            _ => {
+                eprintln!("{}: not a campaign progress file, ignoring", path.display());
+                return progress;
+            }
+        }
+        progress
+            .completed
+            .extend(lines.map_while(Result::ok).filter(|line| !line.is_empty()));
+        progress
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = progress_path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no data directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let temp_path = path.with_extension("tmp");
+        {
+            let mut file = File::create(&temp_path)?;
+            writeln!(file, "{}", PROGRESS_HEADER)?;
+            for level in &self.completed {
+                writeln!(file, "{}", level)?;
+            }
+        }
+        fs::rename(temp_path, path)
+    }
+
+    pub fn is_completed(&self, level: &Level) -> bool {
+        self.completed.contains(&level.file)
+    }
+
+    // The first level is always open and each completed level opens the next
+    // one. Levels whose map is missing don't block the ones after them.
+    pub fn is_unlocked(&self, levels: &[Level], i: usize) -> bool {
+        levels[..i]
+            .iter()
+            .rev()
+            .find(|level| level.map.is_some())
+            .is_none_or(|level| self.is_completed(level))
+            || self.is_completed(&levels[i])
+    }
+
+    pub fn complete(&mut self, file: &str) {
+        self.completed.insert(file.to_string());
+    }
+}
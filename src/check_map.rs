@@ -0,0 +1,50 @@
+// SPDX-FileCopyrightText: 2019 Tuomas Siipola
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use crate::map::{Diagnostic, Map, MapError, Severity};
+
+pub const USAGE: &str = "natrix check-map <file>...";
+
+fn print_diagnostic(path: &str, diagnostic: &Diagnostic) {
+    let severity = match diagnostic.severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    };
+    match diagnostic.column {
+        Some(column) => println!(
+            "{}:{}:{}: {}: {}",
+            path, diagnostic.line, column, severity, diagnostic.message
+        ),
+        None => println!(
+            "{}:{}: {}: {}",
+            path, diagnostic.line, severity, diagnostic.message
+        ),
+    }
+}
+
+// Prints every problem found in the given maps. Returns false if any of them
+// can't be played.
+pub fn run(paths: &[String]) -> bool {
+    let mut ok = true;
+    for path in paths {
+        match Map::check(path) {
+            Ok((_, warnings)) if warnings.is_empty() => println!("{}: ok", path),
+            Ok((_, warnings)) => {
+                for warning in &warnings {
+                    print_diagnostic(path, warning);
+                }
+            }
+            Err(MapError::Io(err)) => {
+                println!("{}: error: {}", path, err);
+                ok = false;
+            }
+            Err(MapError::Invalid(diagnostics)) => {
+                for diagnostic in &diagnostics {
+                    print_diagnostic(path, diagnostic);
+                }
+                ok = false;
+            }
+        }
+    }
+    ok
+}
@@ -0,0 +1,189 @@
+// SPDX-FileCopyrightText: 2019 Tuomas Siipola
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use sdl2::event::{Event, EventPollIterator};
+use sdl2::keyboard::Scancode;
+use sdl2::pixels::Color;
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::Window;
+
+use crate::audio::{self, Audio, Sound};
+use crate::font::Font;
+use crate::input::{Bindings, InputAction, ACTIONS};
+use crate::state::{Action, State};
+
+const ENTRIES: [&str; 4] = ["Volume", "Sound", "Reset to defaults", "Done"];
+
+// The audio settings are copied here so that they can be drawn.
+pub struct Controls {
+    selected: usize,
+    waiting_for_key: bool,
+    message: Option<String>,
+    volume: u8,
+    muted: bool,
+}
+
+impl Controls {
+    pub fn new(audio: &Audio) -> Controls {
+        Controls {
+            selected: 0,
+            waiting_for_key: false,
+            message: None,
+            volume: audio.volume(),
+            muted: audio.muted(),
+        }
+    }
+
+    fn set_volume(&mut self, audio: &mut Audio, volume: u8) {
+        audio.set_volume(volume);
+        self.volume = audio.volume();
+        audio.play(Sound::Navigate);
+    }
+
+    fn toggle_mute(&mut self, audio: &mut Audio) {
+        self.muted = !self.muted;
+        audio.set_muted(self.muted);
+    }
+}
+
+fn save(bindings: &Bindings) {
+    if let Err(err) = bindings.save() {
+        eprintln!("failed to save key bindings: {}", err);
+    }
+}
+
+impl State for Controls {
+    fn handle_events(
+        &mut self,
+        events: EventPollIterator,
+        bindings: &mut Bindings,
+        audio: &mut Audio,
+    ) -> Action {
+        let count = ACTIONS.len() + ENTRIES.len();
+        for event in events {
+            match event {
+                Event::Quit { .. } => return Action::Quit,
+                Event::KeyDown {
+                    scancode: Some(scancode),
+                    repeat: false,
+                    ..
+                } if self.waiting_for_key => {
+                    // Escape always cancels, whatever it is bound to.
+                    if scancode != Scancode::Escape {
+                        match bindings.bind(ACTIONS[self.selected], scancode) {
+                            Ok(()) => save(bindings),
+                            Err(message) => self.message = Some(message),
+                        }
+                    }
+                    self.waiting_for_key = false;
+                }
+                Event::KeyDown { .. } if self.waiting_for_key => {}
+                Event::KeyDown {
+                    scancode: Some(scancode),
+                    ..
+                } => match bindings.action(scancode) {
+                    Some(InputAction::Confirm) => match self.selected.checked_sub(ACTIONS.len()) {
+                        None => {
+                            self.waiting_for_key = true;
+                            self.message = None;
+                        }
+                        Some(0) => {}
+                        Some(1) => self.toggle_mute(audio),
+                        Some(2) => {
+                            *bindings = Bindings::default();
+                            save(bindings);
+                        }
+                        Some(_) => return Action::Pop(None),
+                    },
+                    Some(InputAction::Left) => match self.selected.checked_sub(ACTIONS.len()) {
+                        Some(0) => self.set_volume(audio, self.volume.saturating_sub(1)),
+                        Some(1) => self.toggle_mute(audio),
+                        _ => {}
+                    },
+                    Some(InputAction::Right) => match self.selected.checked_sub(ACTIONS.len()) {
+                        Some(0) => self.set_volume(audio, self.volume + 1),
+                        Some(1) => self.toggle_mute(audio),
+                        _ => {}
+                    },
+                    Some(InputAction::Back) => return Action::Pop(None),
+                    Some(InputAction::Up) => {
+                        audio.play(Sound::Navigate);
+                        if self.selected == 0 {
+                            self.selected = count - 1;
+                        } else {
+                            self.selected -= 1;
+                        }
+                    }
+                    Some(InputAction::Down) => {
+                        audio.play(Sound::Navigate);
+                        if self.selected == count - 1 {
+                            self.selected = 0;
+                        } else {
+                            self.selected += 1;
+                        }
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        Action::None
+    }
+
+    fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        font: &mut Font,
+        _tiles: &Texture,
+        _logo: &Texture,
+        bindings: &Bindings,
+    ) {
+        canvas.set_draw_color(Color::RGB(215, 227, 244));
+        canvas.clear();
+
+        font.draw(
+            canvas,
+            ((320 - font.measure("Options")) / 2) as i32,
+            20,
+            "Options",
+            Color::RGB(0, 0, 0),
+        );
+
+        for (i, action) in ACTIONS.iter().enumerate() {
+            let y = 50 + i as i32 * 10;
+            let x = if i == self.selected { 90 } else { 80 };
+            font.draw(canvas, x, y, action.name(), Color::RGB(0, 0, 0));
+            let keys = if i == self.selected && self.waiting_for_key {
+                "Press a key, Escape cancels".to_string()
+            } else {
+                let names: Vec<&str> = bindings
+                    .keys(*action)
+                    .iter()
+                    .map(|key| key.name())
+                    .collect();
+                names.join(", ")
+            };
+            font.draw(canvas, 160, y, &keys, Color::RGB(0, 0, 0));
+        }
+
+        for (i, entry) in ENTRIES.iter().enumerate() {
+            let text = match i {
+                0 => format!("{}: < {}/{} >", entry, self.volume, audio::MAX_VOLUME),
+                1 => format!("{}: < {} >", entry, if self.muted { "Off" } else { "On" }),
+                _ => entry.to_string(),
+            };
+            let i = ACTIONS.len() + i;
+            font.draw(
+                canvas,
+                if i == self.selected { 90 } else { 80 },
+                55 + i as i32 * 10,
+                &text,
+                Color::RGB(0, 0, 0),
+            );
+        }
+
+        if let Some(message) = &self.message {
+            font.draw(canvas, 1, 230, message, Color::RGB(0, 0, 0));
+        }
+    }
+}
@@ -0,0 +1,420 @@
+// SPDX-FileCopyrightText: 2019 Tuomas Siipola
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use sdl2::event::{Event, EventPollIterator};
+use sdl2::keyboard::{Mod, Scancode};
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::Window;
+
+use crate::audio::Audio;
+use crate::direction::Direction;
+use crate::font::Font;
+use crate::game::Game;
+use crate::input::{Bindings, InputAction};
+use crate::map::{Map, Severity};
+use crate::paths;
+use crate::render;
+use crate::speed::Speed;
+use crate::state::{Action, Signal, State, Transition};
+use crate::tile::Tile;
+
+const MAX_NAME_LENGTH: usize = 30;
+
+#[derive(Copy, Clone, PartialEq)]
+enum Brush {
+    Wall,
+    Empty,
+    Start,
+}
+
+const BRUSHES: [Brush; 3] = [Brush::Wall, Brush::Empty, Brush::Start];
+
+impl Brush {
+    fn name(self) -> &'static str {
+        match self {
+            Brush::Wall => "Wall",
+            Brush::Empty => "Empty",
+            Brush::Start => "Start",
+        }
+    }
+
+    fn next(self) -> Brush {
+        let i = BRUSHES.iter().position(|brush| *brush == self).unwrap();
+        BRUSHES[(i + 1) % BRUSHES.len()]
+    }
+}
+
+fn clockwise(direction: Direction) -> Direction {
+    match direction {
+        Direction::Up => Direction::Right,
+        Direction::Right => Direction::Down,
+        Direction::Down => Direction::Left,
+        Direction::Left => Direction::Up,
+    }
+}
+
+// File name for a map in the player's map directory based on its name.
+fn map_path(dir: &Path, name: &str) -> PathBuf {
+    let mut file_name = String::new();
+    for c in name.trim().chars() {
+        if c.is_ascii_alphanumeric() {
+            file_name.push(c.to_ascii_lowercase());
+        } else if !file_name.ends_with('-') {
+            file_name.push('-');
+        }
+    }
+    let file_name = file_name.trim_matches('-');
+    let file_name = if file_name.is_empty() {
+        "map"
+    } else {
+        file_name
+    };
+    dir.join(format!("{}.map", file_name))
+}
+
+// Paints a new map on the default sized grid. Walls are auto-tiled as they
+// are painted so the map looks like it will in the game.
+pub struct Editor {
+    map: Map,
+    cursor: (usize, usize),
+    brush: Brush,
+    name_entry: Option<String>,
+    help: bool,
+    message: Option<String>,
+    saved_path: Option<PathBuf>,
+    overwrite: Option<PathBuf>,
+    modified: bool,
+    leave_warned: bool,
+}
+
+impl Editor {
+    pub fn new() -> Editor {
+        let mut map = Map::new();
+        map.name = "Untitled".to_string();
+        Editor {
+            cursor: (map.snake_x, map.snake_y),
+            map,
+            brush: Brush::Wall,
+            name_entry: None,
+            help: false,
+            message: None,
+            saved_path: None,
+            overwrite: None,
+            modified: false,
+            leave_warned: false,
+        }
+    }
+
+    fn paint(&mut self, (x, y): (usize, usize), brush: Brush) {
+        match brush {
+            Brush::Wall if (x, y) != (self.map.snake_x, self.map.snake_y) => {
+                self.map.set_tile(x, y, Tile::Wall(0))
+            }
+            Brush::Wall => return,
+            Brush::Empty => self.map.set_tile(x, y, Tile::Empty),
+            Brush::Start => {
+                self.map.set_tile(x, y, Tile::Empty);
+                self.map.snake_x = x;
+                self.map.snake_y = y;
+            }
+        }
+        self.map.autotile();
+        self.changed();
+    }
+
+    fn changed(&mut self) {
+        self.modified = true;
+        self.leave_warned = false;
+        self.overwrite = None;
+    }
+
+    fn move_cursor(&mut self, direction: Direction) {
+        let (x, y) = self.cursor;
+        self.cursor = match direction {
+            Direction::Up => (x, y.saturating_sub(1)),
+            Direction::Right => ((x + 1).min(self.map.width - 1), y),
+            Direction::Down => (x, (y + 1).min(self.map.height - 1)),
+            Direction::Left => (x.saturating_sub(1), y),
+        };
+    }
+
+    // The first thing that would stop the map from being played.
+    fn problem(&self) -> Option<String> {
+        let mut diagnostics = Vec::new();
+        self.map.check_layout(0, &mut diagnostics);
+        diagnostics
+            .into_iter()
+            .find(|diagnostic| diagnostic.severity == Severity::Error)
+            .map(|diagnostic| diagnostic.message)
+    }
+
+    fn test_play(&mut self) -> Action {
+        if let Some(problem) = self.problem() {
+            self.message = Some(format!("Can't play: {}", problem));
+            return Action::None;
+        }
+        let speed = self.map.speed.unwrap_or(Speed::Normal);
+        Action::Push(
+            Box::new(Game::new(&self.map, None, speed)),
+            Transition::Fade,
+        )
+    }
+
+    // Existing files are only overwritten if they were saved from this
+    // editor or the player saves twice in a row.
+    fn save(&mut self) {
+        if let Some(problem) = self.problem() {
+            self.message = Some(format!("Can't save: {}", problem));
+            return;
+        }
+        let dir = match paths::user_maps_dir() {
+            Some(dir) => dir,
+            None => {
+                self.message = Some("Can't save: no data directory".to_string());
+                return;
+            }
+        };
+        if let Err(err) = fs::create_dir_all(&dir) {
+            self.message = Some(format!("Can't save: {}", err));
+            return;
+        }
+        let path = map_path(&dir, &self.map.name);
+        if path.exists()
+            && self.saved_path.as_ref() != Some(&path)
+            && self.overwrite.as_ref() != Some(&path)
+        {
+            self.message = Some(format!(
+                "{} exists, save again to overwrite",
+                path.display()
+            ));
+            self.overwrite = Some(path);
+            return;
+        }
+        self.message = Some(match self.map.save(&path) {
+            Ok(()) => {
+                self.modified = false;
+                self.saved_path = Some(path.clone());
+                format!("Saved {}", path.display())
+            }
+            Err(err) => format!("Can't save: {}", err),
+        });
+        self.overwrite = None;
+    }
+
+    fn handle_name_entry(&mut self, event: &Event) {
+        let name = match &mut self.name_entry {
+            Some(name) => name,
+            None => return,
+        };
+        match event {
+            Event::TextInput { text, .. } => {
+                for c in text.chars() {
+                    if (c == ' ' || c.is_ascii_graphic()) && name.len() < MAX_NAME_LENGTH {
+                        name.push(c);
+                    }
+                }
+            }
+            Event::KeyDown {
+                scancode: Some(scancode),
+                ..
+            } => match scancode {
+                Scancode::Backspace => {
+                    name.pop();
+                }
+                Scancode::Return | Scancode::KpEnter => {
+                    let name = name.trim().to_string();
+                    if !name.is_empty() && name != self.map.name {
+                        self.map.name = name;
+                        self.changed();
+                    }
+                    self.name_entry = None;
+                }
+                Scancode::Escape => self.name_entry = None,
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+impl State for Editor {
+    fn handle_events(
+        &mut self,
+        events: EventPollIterator,
+        bindings: &mut Bindings,
+        _audio: &mut Audio,
+    ) -> Action {
+        for event in events {
+            if let Event::Quit { .. } = event {
+                return Action::Quit;
+            }
+            if self.name_entry.is_some() {
+                self.handle_name_entry(&event);
+                continue;
+            }
+            match event {
+                Event::MouseButtonDown {
+                    mouse_btn, x, y, ..
+                } => {
+                    if let Some(position) = render::tile_at(&self.map, x, y) {
+                        self.cursor = position;
+                        match mouse_btn {
+                            MouseButton::Left => self.paint(position, self.brush),
+                            MouseButton::Right => self.paint(position, Brush::Empty),
+                            _ => {}
+                        }
+                    }
+                }
+                // Dragging paints walls or empty tiles but only the click
+                // moves the start.
+                Event::MouseMotion {
+                    mousestate, x, y, ..
+                } => {
+                    if let Some(position) = render::tile_at(&self.map, x, y) {
+                        self.cursor = position;
+                        if mousestate.left() && self.brush != Brush::Start {
+                            self.paint(position, self.brush);
+                        } else if mousestate.right() {
+                            self.paint(position, Brush::Empty);
+                        }
+                    }
+                }
+                Event::KeyDown {
+                    scancode: Some(scancode),
+                    keymod,
+                    ..
+                } => {
+                    self.message = None;
+                    let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+                    match scancode {
+                        Scancode::F1 => self.help = !self.help,
+                        Scancode::F2 => self.name_entry = Some(self.map.name.clone()),
+                        Scancode::F3 => {
+                            self.map.start_direction = clockwise(self.map.start_direction);
+                            self.changed();
+                        }
+                        Scancode::F4 => {
+                            self.map.set_wrap(!self.map.wrap);
+                            self.message = Some(
+                                if self.map.wrap {
+                                    "Borders wrap around"
+                                } else {
+                                    "Borders are solid"
+                                }
+                                .to_string(),
+                            );
+                            self.changed();
+                        }
+                        Scancode::F5 => return self.test_play(),
+                        Scancode::S if ctrl => self.save(),
+                        Scancode::Num1 => self.brush = Brush::Wall,
+                        Scancode::Num2 => self.brush = Brush::Empty,
+                        Scancode::Num3 => self.brush = Brush::Start,
+                        Scancode::Tab => self.brush = self.brush.next(),
+                        _ => {
+                            let direction = bindings
+                                .action(scancode)
+                                .and_then(InputAction::direction)
+                                .or_else(|| {
+                                    bindings
+                                        .player_direction(scancode)
+                                        .map(|(_, direction)| direction)
+                                });
+                            if let Some(direction) = direction {
+                                self.move_cursor(direction);
+                                continue;
+                            }
+                            match bindings.action(scancode) {
+                                Some(InputAction::Confirm) => self.paint(self.cursor, self.brush),
+                                Some(InputAction::Back) if self.help => self.help = false,
+                                Some(InputAction::Back) if self.modified && !self.leave_warned => {
+                                    self.leave_warned = true;
+                                    self.message = Some(format!(
+                                        "Unsaved changes, press {} again to leave",
+                                        bindings.key_name(InputAction::Back)
+                                    ));
+                                }
+                                Some(InputAction::Back) => {
+                                    return Action::Pop(
+                                        self.saved_path.as_ref().map(|_| Signal::MapsChanged),
+                                    )
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Action::None
+    }
+
+    fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        font: &mut Font,
+        tiles: &Texture,
+        _logo: &Texture,
+        bindings: &Bindings,
+    ) {
+        let mut preview = self.map.clone();
+        preview.set_tile(
+            self.map.snake_x,
+            self.map.snake_y,
+            Tile::SnakeHead(0, self.map.start_direction),
+        );
+        render::draw_game(
+            canvas,
+            font,
+            tiles,
+            &preview,
+            &format!("Brush: {}", self.brush.name()),
+            Some("F1 help"),
+        );
+
+        canvas.set_draw_color(Color::RGB(255, 0, 0));
+        canvas
+            .draw_rect(render::tile_rect(&self.map, self.cursor.0, self.cursor.1))
+            .unwrap();
+
+        if let Some(name) = &self.name_entry {
+            render::draw_dialog(
+                canvas,
+                font,
+                &["Map name:", &format!("{}_", name), "", "Enter to accept"],
+            );
+        } else if self.help {
+            let paint = format!(
+                "{}/left click: paint   right click: erase",
+                bindings.key_name(InputAction::Confirm)
+            );
+            render::draw_dialog(
+                canvas,
+                font,
+                &[
+                    &paint,
+                    "1 wall   2 empty   3 start   Tab: next brush",
+                    "F2: rename   F3: turn start   F4: borders",
+                    "F5: test play   Ctrl+S: save",
+                ],
+            );
+        }
+
+        if let Some(message) = &self.message {
+            let height = 10;
+            let y = (render::SCREEN_HEIGHT - height) as i32;
+            canvas.set_draw_color(Color::RGB(0, 0, 0));
+            canvas
+                .fill_rect(Rect::new(0, y, render::SCREEN_WIDTH, height))
+                .unwrap();
+            font.draw(canvas, 1, y, message, Color::RGB(255, 255, 255));
+        }
+    }
+}
@@ -6,7 +6,6 @@ use sdl2::rect::Rect;
 use sdl2::render::{Canvas, Texture, TextureCreator};
 use sdl2::surface::Surface;
 use sdl2::video::{Window, WindowContext};
-use std::path::Path;
 
 const SPACE_WIDTH: u32 = 2;
 
@@ -22,21 +21,25 @@ struct Character {
 }
 
 impl<'a> Font<'a> {
-    pub fn load_bmp<P: AsRef<Path>>(
-        texture_creator: &TextureCreator<WindowContext>,
-        path: P,
-    ) -> Font {
+    // Characters are separated by magenta pixels on the first row of the
+    // image.
+    pub fn from_surface(
+        texture_creator: &'a TextureCreator<WindowContext>,
+        mut surface: Surface,
+    ) -> Result<Font<'a>, String> {
+        if surface.pixel_format_enum().byte_size_per_pixel() != 3 {
+            return Err("expected a 24-bit image".to_string());
+        }
         let mut characters = [Character { x: 0, width: 0 }; 256];
-        let mut surface = Surface::load_bmp(path).unwrap();
+        let mut count = 0;
         surface.with_lock(|pixels| {
-            let mut count = 0;
             let mut last_x = 0;
 
-            for (x, color) in pixels[3 as usize..(surface.width() * 3) as usize]
+            for (x, color) in pixels[3..(surface.width() * 3) as usize]
                 .chunks(3)
                 .enumerate()
             {
-                if color == [255, 0, 255] {
+                if color == [255, 0, 255] && count < characters.len() {
                     characters[count].x = (last_x + 1) as u32;
                     characters[count].width = (x - last_x) as u32;
                     count += 1;
@@ -44,13 +47,16 @@ impl<'a> Font<'a> {
                 }
             }
         });
-        surface.set_color_key(true, Color::RGB(0, 0, 0)).unwrap();
-        Font {
+        if count == 0 {
+            return Err("no characters found".to_string());
+        }
+        surface.set_color_key(true, Color::RGB(0, 0, 0))?;
+        Ok(Font {
             texture: texture_creator
                 .create_texture_from_surface(surface)
-                .unwrap(),
+                .map_err(|err| err.to_string())?,
             characters,
-        }
+        })
     }
 
     fn get_character(&self, byte: u8) -> Character {
@@ -0,0 +1,68 @@
+// SPDX-FileCopyrightText: 2019 Tuomas Siipola
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Food {
+    Normal,
+    // Disappears if it isn't eaten in time.
+    Bonus,
+    Golden,
+    Shrink,
+    SpeedUp,
+    SlowDown,
+}
+
+pub const FOODS: [Food; 6] = [
+    Food::Normal,
+    Food::Bonus,
+    Food::Golden,
+    Food::Shrink,
+    Food::SpeedUp,
+    Food::SlowDown,
+];
+
+// How many ticks speed changes last.
+pub const EFFECT_TICKS: u32 = 60;
+
+impl Food {
+    // Name used in map files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Food::Normal => "normal",
+            Food::Bonus => "bonus",
+            Food::Golden => "golden",
+            Food::Shrink => "shrink",
+            Food::SpeedUp => "fast",
+            Food::SlowDown => "slow",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Food> {
+        FOODS.iter().cloned().find(|food| food.name() == name)
+    }
+
+    pub fn points(self) -> u32 {
+        match self {
+            Food::Bonus => 3,
+            Food::Golden => 5,
+            _ => 1,
+        }
+    }
+
+    pub fn growth(self) -> u8 {
+        match self {
+            Food::Normal | Food::Golden => 5,
+            Food::Bonus => 3,
+            Food::Shrink => 0,
+            Food::SpeedUp | Food::SlowDown => 2,
+        }
+    }
+
+    // Ticks until uneaten food disappears, if it does.
+    pub fn lifetime(self) -> Option<u32> {
+        match self {
+            Food::Bonus => Some(40),
+            _ => None,
+        }
+    }
+}
@@ -1,125 +1,205 @@
 // SPDX-FileCopyrightText: 2019 Tuomas Siipola
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use rand::{thread_rng, Rng};
-use std::thread;
 use std::time::Duration;
 
-use sdl2::event::{Event, EventPollIterator};
+use sdl2::event::{Event, EventPollIterator, WindowEvent};
 use sdl2::keyboard::Scancode;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
 use sdl2::render::{Canvas, Texture};
 use sdl2::video::Window;
 
-use crate::direction::Direction;
+use crate::audio::{self, Audio, Sound};
+use crate::campaign::{Goal, Progress};
 use crate::font::Font;
+use crate::highscore::{self, HighScores};
+use crate::input::{Bindings, InputAction, TurnQueue};
 use crate::map::Map;
-use crate::menu::Menu;
-use crate::snake::Snake;
-use crate::state::{Action, State};
-use crate::tile::Tile;
+use crate::pause::Pause;
+use crate::recording::Recording;
+use crate::render;
+use crate::simulation::{random_seed, GameEvent, Player, Simulation};
+use crate::speed::Speed;
+use crate::state::{Action, Signal, State, Transition};
+
+const BLINK_INTERVAL: Duration = Duration::from_millis(100);
 
 pub struct Game {
-    snake: Snake,
-    score: u32,
-    snake_alive: bool,
+    simulation: Simulation,
+    turns: TurnQueue,
+    recording: Recording,
+    recording_saved: bool,
     snake_show: bool,
     initial_map: Map,
-    map: Map,
+    seed: Option<u32>,
+    speed: Speed,
+    high_scores: HighScores,
+    name_entry: Option<String>,
+    // File name of the campaign level being played and its goal.
+    level: Option<(String, Goal)>,
+    eaten: u32,
+    // Game time survived, which doesn't include time spent paused.
+    time: Duration,
+    completed: bool,
 }
 
 impl Game {
-    fn redraw(canvas: &mut Canvas<Window>, font: &mut Font, tiles: &Texture, game: &Game) {
-        canvas.set_draw_color(Color::RGB(255, 255, 255));
-        canvas.clear();
+    fn player(&self) -> &Player {
+        &self.simulation.players[0]
+    }
 
-        canvas.set_draw_color(Color::RGB(0, 0, 0));
-        canvas.fill_rect(Rect::new(0, 0, 320, 10)).unwrap();
-        font.draw(
-            canvas,
-            1,
-            0,
-            &format!("Score: {}", game.score),
-            Color::RGB(255, 255, 255),
-        );
-        font.draw(
-            canvas,
-            ((320 - font.measure(&game.map.name)) / 2) as i32,
-            0,
-            &game.map.name,
-            Color::RGB(255, 255, 255),
-        );
+    fn score(&self) -> String {
+        format!("Score: {}", self.player().score)
+    }
 
-        for x in 0..32 {
-            for y in 0..23 {
-                update_tile(canvas, tiles, &game.map, x, y);
+    fn status(&self) -> String {
+        match &self.level {
+            Some((_, goal)) => goal.progress(self.eaten, self.time),
+            None => format!("{}   Seed: {}", self.speed.name(), self.simulation.seed),
+        }
+    }
+
+    fn pause(&self) -> Action {
+        Action::Push(
+            Box::new(Pause::new(
+                self.simulation.map.clone(),
+                self.score(),
+                self.status(),
+            )),
+            Transition::None,
+        )
+    }
+
+    // Restarting builds a fresh game so no state from the previous round can
+    // leak into the next one.
+    fn restart(&self) -> Action {
+        let mut game = Game::new(&self.initial_map, self.seed, self.speed);
+        game.level = self.level.clone();
+        Action::Replace(Box::new(game), Transition::Fade)
+    }
+
+    fn complete_level(&mut self) {
+        self.completed = true;
+        if let Some((file, _)) = &self.level {
+            let mut progress = Progress::load();
+            progress.complete(file);
+            if let Err(err) = progress.save() {
+                eprintln!("failed to save campaign progress: {}", err);
             }
         }
     }
 
-    pub fn new(canvas: &mut Canvas<Window>, font: &mut Font, tiles: &Texture, map: &Map) -> Game {
-        let mut game = Game {
-            snake: Snake::new(map.snake_x as i32, map.snake_y as i32, Direction::Right),
-            snake_alive: true,
+    fn submit_high_score(&mut self, name: &str) {
+        let name = if name.is_empty() { "Anonymous" } else { name };
+        self.high_scores
+            .insert(&self.initial_map, name, self.player().score);
+        if let Err(err) = self.high_scores.save() {
+            eprintln!("failed to save high scores: {}", err);
+        }
+    }
+
+    pub fn new(map: &Map, seed: Option<u32>, speed: Speed) -> Game {
+        let simulation = Simulation::new(map, seed.unwrap_or_else(random_seed), 1);
+        let recording = Recording::new(&map.name, map.content_hash(), simulation.seed, speed);
+        Game {
+            simulation,
+            turns: TurnQueue::new(),
+            recording,
+            recording_saved: false,
             snake_show: true,
-            score: 0,
             initial_map: map.clone(),
-            map: map.clone(),
-        };
-
-        Game::redraw(canvas, font, tiles, &game);
-
-        place_food(&mut game.map, canvas, tiles);
+            seed,
+            speed,
+            high_scores: HighScores::load(),
+            name_entry: None,
+            level: None,
+            eaten: 0,
+            time: Duration::from_secs(0),
+            completed: false,
+        }
+    }
 
+    pub fn level(map: &Map, speed: Speed, file: &str, goal: Goal) -> Game {
+        let mut game = Game::new(map, None, speed);
+        game.level = Some((file.to_string(), goal));
         game
     }
 }
 
 impl State for Game {
-    fn update(
+    fn handle_events(
         &mut self,
         events: EventPollIterator,
-        canvas: &mut Canvas<Window>,
-        font: &mut Font,
-        tiles: &Texture,
-        _logo: &Texture,
+        bindings: &mut Bindings,
+        audio: &mut Audio,
     ) -> Action {
-        let mut next_direction = self.snake.head.direction;
-
         for event in events {
             match event {
                 Event::Quit { .. } => return Action::Quit,
+                Event::Window {
+                    win_event: WindowEvent::FocusLost,
+                    ..
+                } if self.player().alive && !self.completed => return self.pause(),
+                Event::TextInput { text, .. } => {
+                    if let Some(name) = &mut self.name_entry {
+                        for c in text.chars() {
+                            if (c == ' ' || c.is_ascii_graphic())
+                                && name.len() < highscore::MAX_NAME_LENGTH
+                            {
+                                name.push(c);
+                            }
+                        }
+                    }
+                }
                 Event::KeyDown {
                     scancode: Some(scancode),
                     ..
                 } => {
-                    if self.snake_alive {
-                        next_direction = match scancode {
-                            Scancode::W => Direction::Up,
-                            Scancode::D => Direction::Right,
-                            Scancode::S => Direction::Down,
-                            Scancode::A => Direction::Left,
-                            _ => next_direction,
+                    if let Some(name) = &mut self.name_entry {
+                        match scancode {
+                            Scancode::Backspace => {
+                                name.pop();
+                            }
+                            Scancode::Return | Scancode::KpEnter => {
+                                let name = name.trim().to_string();
+                                self.submit_high_score(&name);
+                                self.name_entry = None;
+                            }
+                            Scancode::Escape => self.name_entry = None,
+                            _ => {}
+                        }
+                    } else if self.completed {
+                        match bindings.action(scancode) {
+                            Some(InputAction::Confirm) => {
+                                return Action::Pop(Some(Signal::NextLevel));
+                            }
+                            Some(InputAction::Back) => return Action::Pop(None),
+                            _ => {}
+                        }
+                    } else if self.player().alive {
+                        match bindings.action(scancode) {
+                            Some(InputAction::Pause) | Some(InputAction::Back) => {
+                                return self.pause();
+                            }
+                            Some(action) => {
+                                if let Some(direction) = action.direction() {
+                                    self.turns
+                                        .push(direction, self.player().snake.head.direction);
+                                }
+                            }
+                            None => {}
                         }
                     } else {
-                        match scancode {
-                            Scancode::R => {
-                                self.map = self.initial_map.clone();
-                                self.snake = Snake::new(
-                                    self.map.snake_x as i32,
-                                    self.map.snake_y as i32,
-                                    Direction::Right,
-                                );
-                                self.snake_alive = true;
-                                self.snake_show = true;
-                                self.score = 0;
-                                place_food(&mut self.map, canvas, tiles);
-                                Game::redraw(canvas, font, tiles, self);
-                                return Action::None;
+                        match bindings.action(scancode) {
+                            Some(InputAction::Restart) => {
+                                audio.play(Sound::Start);
+                                return self.restart();
                             }
-                            Scancode::M => {
-                                return Action::Change(Box::new(Menu::new()));
+                            Some(InputAction::Back) => return Action::Pop(None),
+                            Some(InputAction::Confirm) if !self.recording_saved => {
+                                match self.recording.save_new() {
+                                    Ok(_) => self.recording_saved = true,
+                                    Err(err) => eprintln!("failed to save replay: {}", err),
+                                }
                             }
                             _ => {}
                         }
@@ -129,219 +209,153 @@ impl State for Game {
             }
         }
 
-        if self.snake_alive {
-            if self.snake.grow > 0 {
-                self.snake.grow -= 1;
-            } else {
-                self.map.tiles[self.snake.tail.x as usize][self.snake.tail.y as usize] =
-                    Tile::Empty;
-
-                update_tile(
-                    canvas,
-                    tiles,
-                    &self.map,
-                    self.snake.tail.x,
-                    self.snake.tail.y,
-                );
-
-                self.snake.tail.update();
-
-                match self.map.tiles[self.snake.tail.x as usize][self.snake.tail.y as usize] {
-                    Tile::SnakeTurn(direction, _) => self.snake.tail.direction = direction,
-                    Tile::SnakeVertical | Tile::SnakeHorizontal => {}
-                    _ => unreachable!(),
-                };
-
-                self.map.tiles[self.snake.tail.x as usize][self.snake.tail.y as usize] =
-                    Tile::SnakeTail(self.snake.tail.direction);
-
-                update_tile(
-                    canvas,
-                    tiles,
-                    &self.map,
-                    self.snake.tail.x,
-                    self.snake.tail.y,
-                );
-            }
+        Action::None
+    }
 
-            if self.snake.head.direction != next_direction
-                && next_direction.opposite() != self.snake.head.direction
-            {
-                self.map.tiles[self.snake.head.x as usize][self.snake.head.y as usize] =
-                    Tile::SnakeTurn(
-                        next_direction,
-                        match (self.snake.head.direction, next_direction) {
-                            (Direction::Right, Direction::Down)
-                            | (Direction::Down, Direction::Left)
-                            | (Direction::Left, Direction::Up)
-                            | (Direction::Up, Direction::Right) => true,
-                            _ => false,
-                        },
-                    );
-                self.snake.head.direction = next_direction;
-            } else {
-                self.map.tiles[self.snake.head.x as usize][self.snake.head.y as usize] =
-                    match self.snake.head.direction {
-                        Direction::Up | Direction::Down => Tile::SnakeVertical,
-                        Direction::Right | Direction::Left => Tile::SnakeHorizontal,
-                    };
+    fn tick(&mut self, audio: &mut Audio) -> Action {
+        if self.completed {
+            return Action::None;
+        }
+        if self.player().alive {
+            self.time += self.tick_interval();
+            let next_direction = self.turns.next(self.player().snake.head.direction);
+            if next_direction != self.player().snake.head.direction {
+                audio.play(Sound::Turn);
             }
-
-            update_tile(
-                canvas,
-                tiles,
-                &self.map,
-                self.snake.head.x,
-                self.snake.head.y,
-            );
-
-            self.snake.head.update();
-
-            match self.map.tiles[self.snake.head.x as usize][self.snake.head.y as usize] {
-                Tile::Food => {
-                    place_food(&mut self.map, canvas, tiles);
-                    self.snake.grow += 5;
-                    self.score += 1;
-                    let text = format!("Score: {}", self.score);
-                    canvas.set_draw_color(Color::RGB(0, 0, 0));
-                    canvas
-                        .fill_rect(Rect::new(0, 0, font.measure(&text), 10))
-                        .unwrap();
-                    font.draw(canvas, 1, 0, &text, Color::RGB(255, 255, 255));
-                }
-                Tile::Wall(_)
-                | Tile::SnakeVertical
-                | Tile::SnakeHorizontal
-                | Tile::SnakeTurn(_, _)
-                | Tile::SnakeTail(_) => {
-                    self.snake_alive = false;
+            self.recording.inputs.push(next_direction);
+            let events = self.simulation.step(&[next_direction]);
+            audio.play_events(&events);
+            for event in events {
+                if let GameEvent::FoodEaten(0, _) = event {
+                    self.eaten += 1;
                 }
-                _ => {}
-            }
-
-            if self.snake_alive {
-                self.map.tiles[self.snake.head.x as usize][self.snake.head.y as usize] =
-                    Tile::SnakeHead(self.snake.head.direction);
-                update_tile(
-                    canvas,
-                    tiles,
-                    &self.map,
-                    self.snake.head.x,
-                    self.snake.head.y,
-                );
-            }
-        } else {
-            for x in 0..32 {
-                for y in 0..23 {
-                    if !self.snake_show
-                        && match self.map.tiles[x as usize][y as usize] {
-                            Tile::SnakeVertical
-                            | Tile::SnakeHorizontal
-                            | Tile::SnakeTurn(_, _)
-                            | Tile::SnakeTail(_) => true,
-                            _ => false,
-                        }
-                    {
-                        let target_rect = Rect::new(x * 10, 10 + y * 10, 10, 10);
-                        canvas.set_draw_color(Color::RGB(215, 227, 244));
-                        canvas.fill_rect(target_rect).unwrap();
-                    } else {
-                        update_tile(canvas, tiles, &self.map, x, y);
-                    }
+                if event == GameEvent::Died(0)
+                    && self
+                        .high_scores
+                        .qualifies(&self.initial_map, self.player().score)
+                {
+                    self.name_entry = Some(String::new());
                 }
             }
-            if self.snake_show {
-                font.draw(
-                    canvas,
-                    320 - 1 - font.measure("R restart   M menu") as i32,
-                    0,
-                    "R restart   M menu",
-                    Color::RGB(255, 255, 255),
-                );
-            } else {
-                canvas.set_draw_color(Color::RGB(0, 0, 0));
-                canvas.fill_rect(Rect::new(200, 0, 120, 10)).unwrap();
+            let reached = match &self.level {
+                Some((_, goal)) => goal.reached(self.eaten, self.time),
+                None => false,
+            };
+            if reached && self.player().alive {
+                self.complete_level();
             }
+        } else if self.name_entry.is_none() {
             self.snake_show = !self.snake_show;
         }
-
-        canvas.present();
-
-        thread::sleep(Duration::from_millis(100));
-
         Action::None
     }
-}
 
-fn has_free_space(map: &Map) -> bool {
-    for x in 0..32 {
-        for y in 0..23 {
-            if map.tiles[x][y].is_empty() {
-                return true;
-            }
+    fn tick_interval(&self) -> Duration {
+        if self.player().alive {
+            let interval = self.speed.tick_interval(self.player().score);
+            self.simulation.adjust_interval(interval)
+        } else {
+            BLINK_INTERVAL
         }
     }
-    return false;
-}
 
-fn place_food(map: &mut Map, canvas: &mut Canvas<Window>, tiles: &Texture) {
-    if !has_free_space(map) {
-        return;
+    fn music(&self) -> Option<&str> {
+        Some(audio::map_music(&self.simulation.map))
     }
-    let mut rng = thread_rng();
-    loop {
-        let (x, y) = (rng.gen_range(0, 32), rng.gen_range(0, 23));
-        if map.tiles[x as usize][y as usize] != Tile::Empty {
-            continue;
+
+    fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        font: &mut Font,
+        tiles: &Texture,
+        _logo: &Texture,
+        bindings: &Bindings,
+    ) {
+        if self.completed {
+            render::draw_game(
+                canvas,
+                font,
+                tiles,
+                &self.simulation.map,
+                &self.score(),
+                Some(&self.status()),
+            );
+            let keys = format!(
+                "{} next level   {} levels",
+                bindings.key_name(InputAction::Confirm),
+                bindings.key_name(InputAction::Back)
+            );
+            render::draw_dialog(canvas, font, &["Level complete!", &keys]);
+        } else if self.player().alive {
+            render::draw_game(
+                canvas,
+                font,
+                tiles,
+                &self.simulation.map,
+                &self.score(),
+                Some(&self.status()),
+            );
+            render::draw_timers(
+                canvas,
+                font,
+                tiles,
+                &self.score(),
+                &self.simulation.timers(),
+            );
+        } else if let Some(name) = &self.name_entry {
+            render::draw_game(
+                canvas,
+                font,
+                tiles,
+                &self.simulation.map,
+                &self.score(),
+                None,
+            );
+            render::draw_dialog(
+                canvas,
+                font,
+                &[
+                    "New high score!",
+                    &format!("Name: {}_", name),
+                    "Enter to save",
+                ],
+            );
+        } else {
+            let mut status = format!(
+                "{} restart   {} menu",
+                bindings.key_name(InputAction::Restart),
+                bindings.key_name(InputAction::Back)
+            );
+            if !self.recording_saved {
+                status = format!(
+                    "{} save   {}",
+                    bindings.key_name(InputAction::Confirm),
+                    status
+                );
+            }
+            render::draw_game(
+                canvas,
+                font,
+                tiles,
+                &self.simulation.map,
+                &self.score(),
+                if self.snake_show {
+                    Some(status.as_str())
+                } else {
+                    None
+                },
+            );
+            if !self.snake_show {
+                render::hide_snake(canvas, &self.simulation.map);
+            }
         }
-        map.tiles[x as usize][y as usize] = Tile::Food;
-        update_tile(canvas, tiles, map, x, y);
-        break;
     }
-}
 
-fn update_tile(canvas: &mut Canvas<Window>, tiles: &Texture, map: &Map, x: i32, y: i32) {
-    let target_rect = Rect::new(x * 10, 10 + y * 10, 10, 10);
-    match map.tiles[x as usize][y as usize] {
-        Tile::Empty => {
-            canvas.set_draw_color(Color::RGB(215, 227, 244));
-            canvas.fill_rect(target_rect).unwrap();
-        }
-        tile => {
-            canvas
-                .copy(
-                    tiles,
-                    Some(Rect::new(
-                        match tile {
-                            Tile::Wall(i) => 150 + 10 * i32::from(i),
-                            Tile::Food => 140,
-                            Tile::SnakeVertical => 120,
-                            Tile::SnakeHorizontal => 130,
-                            Tile::SnakeTail(Direction::Up) => 60,
-                            Tile::SnakeTail(Direction::Right) => 70,
-                            Tile::SnakeTail(Direction::Down) => 40,
-                            Tile::SnakeTail(Direction::Left) => 50,
-                            Tile::SnakeHead(Direction::Up) => 0,
-                            Tile::SnakeHead(Direction::Right) => 10,
-                            Tile::SnakeHead(Direction::Down) => 20,
-                            Tile::SnakeHead(Direction::Left) => 30,
-                            Tile::SnakeTurn(Direction::Up, false)
-                            | Tile::SnakeTurn(Direction::Left, true) => 110,
-                            Tile::SnakeTurn(Direction::Right, false)
-                            | Tile::SnakeTurn(Direction::Up, true) => 80,
-                            Tile::SnakeTurn(Direction::Down, false)
-                            | Tile::SnakeTurn(Direction::Right, true) => 90,
-                            Tile::SnakeTurn(Direction::Left, false)
-                            | Tile::SnakeTurn(Direction::Down, true) => 100,
-                            _ => unreachable!(),
-                        },
-                        0,
-                        10,
-                        10,
-                    )),
-                    Some(target_rect),
-                )
-                .unwrap();
+    fn resume(&mut self, signal: Option<Signal>) -> Action {
+        match signal {
+            Some(Signal::ExitToMenu) => Action::Pop(None),
+            Some(Signal::Restart) => self.restart(),
+            Some(Signal::NextLevel) | Some(Signal::MapsChanged) | None => Action::None,
         }
     }
 }
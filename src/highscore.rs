@@ -0,0 +1,139 @@
+// SPDX-FileCopyrightText: 2019 Tuomas Siipola
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::io::{self, BufReader};
+use std::path::PathBuf;
+
+use crate::map::Map;
+use crate::paths;
+
+const HEADER: &str = "natrix-highscores 1";
+
+pub const MAX_ENTRIES: usize = 10;
+pub const MAX_NAME_LENGTH: usize = 12;
+
+pub struct Entry {
+    pub name: String,
+    pub score: u32,
+}
+
+// Tables are keyed by map name and content hash so that an edited map starts
+// with an empty table.
+pub struct HighScores {
+    tables: HashMap<(String, u64), Vec<Entry>>,
+}
+
+fn path() -> Option<PathBuf> {
+    paths::data_dir().map(|path| path.join("highscores"))
+}
+
+fn parse_line(line: &str) -> Option<((String, u64), Entry)> {
+    let mut parts = line.splitn(4, '\t');
+    let hash = u64::from_str_radix(parts.next()?, 16).ok()?;
+    let score = parts.next()?.parse().ok()?;
+    let name = parts.next()?.to_string();
+    let map_name = parts.next()?.to_string();
+    Some(((map_name, hash), Entry { name, score }))
+}
+
+impl HighScores {
+    pub fn load() -> HighScores {
+        let mut high_scores = HighScores {
+            tables: HashMap::new(),
+        };
+        let path = match path() {
+            Some(path) => path,
+            None => return high_scores,
+        };
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return high_scores,
+            Err(err) => {
+                eprintln!("{}: {}", path.display(), err);
+                return high_scores;
+            }
+        };
+        let mut lines = BufReader::new(file).lines();
+        match lines.next() {
+            Some(Ok(ref header)) if header == HEADER => {}
+            _ => {
+                eprintln!("{}: not a high score file, ignoring", path.display());
+                return high_scores;
+            }
+        }
+        for (i, line) in lines.enumerate() {
+            match line.ok().as_ref().and_then(|line| parse_line(line)) {
+                Some((key, entry)) => high_scores.tables.entry(key).or_default().push(entry),
+                None => eprintln!("{}:{}: invalid entry, ignoring", path.display(), i + 2),
+            }
+        }
+        for table in high_scores.tables.values_mut() {
+            table.sort_by_key(|entry| Reverse(entry.score));
+            table.truncate(MAX_ENTRIES);
+        }
+        high_scores
+    }
+
+    // Written to a temporary file first so that a crash never leaves a
+    // truncated score file behind.
+    pub fn save(&self) -> io::Result<()> {
+        let path =
+            path().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no data directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let temp_path = path.with_extension("tmp");
+        {
+            let mut file = File::create(&temp_path)?;
+            writeln!(file, "{}", HEADER)?;
+            for ((map_name, hash), table) in &self.tables {
+                for entry in table {
+                    writeln!(
+                        file,
+                        "{:016x}\t{}\t{}\t{}",
+                        hash, entry.score, entry.name, map_name
+                    )?;
+                }
+            }
+        }
+        fs::rename(temp_path, path)
+    }
+
+    fn table(&self, map: &Map) -> &[Entry] {
+        self.tables
+            .get(&(map.name.clone(), map.content_hash()))
+            .map_or(&[], |table| table.as_slice())
+    }
+
+    pub fn best(&self, map: &Map) -> Option<u32> {
+        self.table(map).first().map(|entry| entry.score)
+    }
+
+    pub fn qualifies(&self, map: &Map, score: u32) -> bool {
+        let table = self.table(map);
+        score > 0 && (table.len() < MAX_ENTRIES || table.iter().any(|entry| score > entry.score))
+    }
+
+    pub fn insert(&mut self, map: &Map, name: &str, score: u32) {
+        let table = self
+            .tables
+            .entry((map.name.clone(), map.content_hash()))
+            .or_default();
+        let position = table
+            .iter()
+            .position(|entry| score > entry.score)
+            .unwrap_or(table.len());
+        table.insert(
+            position,
+            Entry {
+                name: name.to_string(),
+                score,
+            },
+        );
+        table.truncate(MAX_ENTRIES);
+    }
+}
@@ -0,0 +1,411 @@
+// SPDX-FileCopyrightText: 2019 Tuomas Siipola
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::io::{self, BufReader};
+use std::path::PathBuf;
+
+use sdl2::keyboard::Scancode;
+
+use crate::direction::Direction;
+use crate::paths;
+
+const MAX_KEYS: usize = 2;
+const MAX_QUEUED_TURNS: usize = 3;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum InputAction {
+    Up,
+    Down,
+    Left,
+    Right,
+    Confirm,
+    Back,
+    Pause,
+    Restart,
+    Player2Up,
+    Player2Down,
+    Player2Left,
+    Player2Right,
+}
+
+pub const ACTIONS: [InputAction; 12] = [
+    InputAction::Up,
+    InputAction::Down,
+    InputAction::Left,
+    InputAction::Right,
+    InputAction::Confirm,
+    InputAction::Back,
+    InputAction::Pause,
+    InputAction::Restart,
+    InputAction::Player2Up,
+    InputAction::Player2Down,
+    InputAction::Player2Left,
+    InputAction::Player2Right,
+];
+
+impl InputAction {
+    pub fn name(self) -> &'static str {
+        match self {
+            InputAction::Up => "Up",
+            InputAction::Down => "Down",
+            InputAction::Left => "Left",
+            InputAction::Right => "Right",
+            InputAction::Confirm => "Confirm",
+            InputAction::Back => "Back",
+            InputAction::Pause => "Pause",
+            InputAction::Restart => "Restart",
+            InputAction::Player2Up => "P2 Up",
+            InputAction::Player2Down => "P2 Down",
+            InputAction::Player2Left => "P2 Left",
+            InputAction::Player2Right => "P2 Right",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<InputAction> {
+        ACTIONS
+            .iter()
+            .cloned()
+            .find(|action| action.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn direction(self) -> Option<Direction> {
+        match self {
+            InputAction::Up => Some(Direction::Up),
+            InputAction::Down => Some(Direction::Down),
+            InputAction::Left => Some(Direction::Left),
+            InputAction::Right => Some(Direction::Right),
+            InputAction::Player2Up => Some(Direction::Up),
+            InputAction::Player2Down => Some(Direction::Down),
+            InputAction::Player2Left => Some(Direction::Left),
+            InputAction::Player2Right => Some(Direction::Right),
+            _ => None,
+        }
+    }
+
+    // Player 2 actions are only used in versus mode, so they may share keys
+    // with the other actions.
+    pub fn player(self) -> usize {
+        match self {
+            InputAction::Player2Up
+            | InputAction::Player2Down
+            | InputAction::Player2Left
+            | InputAction::Player2Right => 1,
+            _ => 0,
+        }
+    }
+}
+
+// The menus can't be used without these, so they always keep at least one key.
+const REQUIRED: [InputAction; 2] = [InputAction::Confirm, InputAction::Back];
+
+pub struct Bindings {
+    keys: Vec<(InputAction, Vec<Scancode>)>,
+}
+
+fn path() -> Option<PathBuf> {
+    paths::config_dir().map(|path| path.join("keys"))
+}
+
+impl Default for Bindings {
+    fn default() -> Bindings {
+        Bindings {
+            keys: vec![
+                (InputAction::Up, vec![Scancode::W, Scancode::Up]),
+                (InputAction::Down, vec![Scancode::S, Scancode::Down]),
+                (InputAction::Left, vec![Scancode::A, Scancode::Left]),
+                (InputAction::Right, vec![Scancode::D, Scancode::Right]),
+                (
+                    InputAction::Confirm,
+                    vec![Scancode::Space, Scancode::Return],
+                ),
+                (InputAction::Back, vec![Scancode::M, Scancode::Escape]),
+                (InputAction::Pause, vec![Scancode::P]),
+                (InputAction::Restart, vec![Scancode::R]),
+                (InputAction::Player2Up, vec![Scancode::Up]),
+                (InputAction::Player2Down, vec![Scancode::Down]),
+                (InputAction::Player2Left, vec![Scancode::Left]),
+                (InputAction::Player2Right, vec![Scancode::Right]),
+            ],
+        }
+    }
+}
+
+impl Bindings {
+    // Actions missing from the file keep their default keys, so adding a new
+    // action never leaves it unbound for existing users.
+    pub fn load() -> Bindings {
+        let mut bindings = Bindings::default();
+        let path = match path() {
+            Some(path) => path,
+            None => return bindings,
+        };
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return bindings,
+            Err(err) => {
+                eprintln!("{}: {}", path.display(), err);
+                return bindings;
+            }
+        };
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => {
+                    eprintln!("{}: {}", path.display(), err);
+                    break;
+                }
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let action = parts
+                .next()
+                .and_then(|name| InputAction::from_name(name.trim()));
+            let keys: Option<Vec<Scancode>> = parts.next().and_then(|keys| {
+                keys.split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(Scancode::from_name)
+                    .collect()
+            });
+            match (action, keys) {
+                (Some(action), Some(keys)) => {
+                    bindings.clear(action);
+                    for key in keys.into_iter().take(MAX_KEYS) {
+                        bindings.assign(action, key);
+                    }
+                }
+                _ => eprintln!("{}:{}: invalid binding, ignoring", path.display(), i + 1),
+            }
+        }
+        for action in bindings.restore_required() {
+            eprintln!(
+                "{}: {} has no keys, using the default ones",
+                path.display(),
+                action.name()
+            );
+        }
+        bindings
+    }
+
+    // Gives actions in REQUIRED that have no keys their default keys back and
+    // returns them.
+    fn restore_required(&mut self) -> Vec<InputAction> {
+        let defaults = Bindings::default();
+        let mut restored = Vec::new();
+        for action in REQUIRED.iter() {
+            if self.keys(*action).is_empty() {
+                for key in defaults.keys(*action) {
+                    self.assign(*action, *key);
+                }
+                restored.push(*action);
+            }
+        }
+        restored
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path =
+            path().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = File::create(path)?;
+        for (action, keys) in &self.keys {
+            let names: Vec<&str> = keys.iter().map(|key| key.name()).collect();
+            writeln!(
+                file,
+                "{} = {}",
+                action.name().to_lowercase(),
+                names.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn action(&self, scancode: Scancode) -> Option<InputAction> {
+        self.keys
+            .iter()
+            .find(|(action, keys)| action.player() == 0 && keys.contains(&scancode))
+            .map(|(action, _)| *action)
+    }
+
+    // In versus mode keys bound to player 2 take precedence, so the arrow keys
+    // steer the second snake even though they also move the first one by
+    // default.
+    pub fn player_direction(&self, scancode: Scancode) -> Option<(usize, Direction)> {
+        let find = |player| {
+            self.keys
+                .iter()
+                .filter(|(action, _)| action.player() == player)
+                .find(|(_, keys)| keys.contains(&scancode))
+                .and_then(|(action, _)| action.direction())
+                .map(|direction| (player, direction))
+        };
+        find(1).or_else(|| find(0))
+    }
+
+    pub fn keys(&self, action: InputAction) -> &[Scancode] {
+        self.keys
+            .iter()
+            .find(|(a, _)| *a == action)
+            .map_or(&[], |(_, keys)| keys.as_slice())
+    }
+
+    pub fn key_name(&self, action: InputAction) -> &str {
+        self.keys(action).first().map_or("?", |key| key.name())
+    }
+
+    fn clear(&mut self, action: InputAction) {
+        for (a, keys) in &mut self.keys {
+            if *a == action {
+                keys.clear();
+            }
+        }
+    }
+
+    // Fails if the key is the last one of an action in REQUIRED.
+    pub fn bind(&mut self, action: InputAction, scancode: Scancode) -> Result<(), String> {
+        for required in REQUIRED.iter() {
+            if *required != action
+                && required.player() == action.player()
+                && self.keys(*required) == [scancode]
+            {
+                return Err(format!("{} needs a key", required.name()));
+            }
+        }
+        self.assign(action, scancode);
+        Ok(())
+    }
+
+    // A key can only trigger one action per player, so binding it removes it
+    // from the player's other actions. Each action keeps its most recent keys.
+    fn assign(&mut self, action: InputAction, scancode: Scancode) {
+        for (a, keys) in &mut self.keys {
+            if a.player() == action.player() {
+                keys.retain(|key| *key != scancode);
+            }
+            if *a == action {
+                keys.push(scancode);
+                if keys.len() > MAX_KEYS {
+                    keys.remove(0);
+                }
+            }
+        }
+    }
+}
+
+// Turns pressed faster than the snake moves are queued and applied one per
+// tick. Each turn is checked against the direction that will be in effect
+// when it is applied, not the current one, so quick U-turns work.
+pub struct TurnQueue {
+    turns: VecDeque<Direction>,
+}
+
+impl TurnQueue {
+    pub fn new() -> TurnQueue {
+        TurnQueue {
+            turns: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, direction: Direction, current: Direction) {
+        let last = self.turns.back().cloned().unwrap_or(current);
+        if direction != last && direction != last.opposite() && self.turns.len() < MAX_QUEUED_TURNS
+        {
+            self.turns.push_back(direction);
+        }
+    }
+
+    pub fn next(&mut self, current: Direction) -> Direction {
+        while let Some(direction) = self.turns.pop_front() {
+            if direction != current && direction != current.opposite() {
+                return direction;
+            }
+        }
+        current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_confirm_and_back_keys_stay() {
+        let mut bindings = Bindings::default();
+        bindings.bind(InputAction::Up, Scancode::Space).unwrap();
+        assert!(bindings.bind(InputAction::Down, Scancode::Return).is_err());
+        assert_eq!(bindings.keys(InputAction::Confirm), [Scancode::Return]);
+        bindings.bind(InputAction::Pause, Scancode::M).unwrap();
+        assert!(bindings.bind(InputAction::Pause, Scancode::Escape).is_err());
+        assert_eq!(bindings.keys(InputAction::Back), [Scancode::Escape]);
+        bindings
+            .bind(InputAction::Confirm, Scancode::Return)
+            .unwrap();
+    }
+
+    #[test]
+    fn empty_confirm_gets_default_keys() {
+        let mut bindings = Bindings::default();
+        bindings.assign(InputAction::Up, Scancode::Space);
+        bindings.assign(InputAction::Up, Scancode::Return);
+        assert!(bindings.keys(InputAction::Confirm).is_empty());
+        assert!(bindings.restore_required() == [InputAction::Confirm]);
+        assert_eq!(
+            bindings.keys(InputAction::Confirm),
+            [Scancode::Space, Scancode::Return]
+        );
+        assert!(bindings.keys(InputAction::Up).is_empty());
+        assert!(bindings.restore_required().is_empty());
+    }
+
+    #[test]
+    fn reverse_turn_is_ignored() {
+        let mut queue = TurnQueue::new();
+        queue.push(Direction::Left, Direction::Right);
+        assert!(queue.next(Direction::Right) == Direction::Right);
+    }
+
+    #[test]
+    fn quick_u_turn_is_queued() {
+        let mut queue = TurnQueue::new();
+        queue.push(Direction::Up, Direction::Right);
+        queue.push(Direction::Left, Direction::Right);
+        assert!(queue.next(Direction::Right) == Direction::Up);
+        assert!(queue.next(Direction::Up) == Direction::Left);
+        assert!(queue.next(Direction::Left) == Direction::Left);
+    }
+
+    #[test]
+    fn reverse_of_queued_turn_is_ignored() {
+        let mut queue = TurnQueue::new();
+        queue.push(Direction::Up, Direction::Right);
+        queue.push(Direction::Down, Direction::Right);
+        assert!(queue.next(Direction::Right) == Direction::Up);
+        assert!(queue.next(Direction::Up) == Direction::Up);
+    }
+
+    #[test]
+    fn queue_is_limited() {
+        let mut queue = TurnQueue::new();
+        let turns = [
+            Direction::Up,
+            Direction::Left,
+            Direction::Down,
+            Direction::Right,
+        ];
+        for direction in turns.iter() {
+            queue.push(*direction, Direction::Right);
+        }
+        assert!(queue.next(Direction::Right) == Direction::Up);
+        assert!(queue.next(Direction::Up) == Direction::Left);
+        assert!(queue.next(Direction::Left) == Direction::Down);
+        assert!(queue.next(Direction::Down) == Direction::Down);
+    }
+}
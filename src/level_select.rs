@@ -0,0 +1,203 @@
+// SPDX-FileCopyrightText: 2019 Tuomas Siipola
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use sdl2::event::{Event, EventPollIterator};
+use sdl2::pixels::Color;
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::Window;
+
+use crate::assets;
+use crate::audio::{Audio, Sound};
+use crate::campaign::{self, Level, Progress};
+use crate::font::Font;
+use crate::game::Game;
+use crate::input::{Bindings, InputAction};
+use crate::speed::Speed;
+use crate::state::{Action, Signal, State, Transition};
+
+pub struct LevelSelect {
+    levels: Vec<Level>,
+    progress: Progress,
+    selected: usize,
+    message: Option<String>,
+}
+
+impl LevelSelect {
+    pub fn new() -> LevelSelect {
+        let levels = match assets::read("campaign") {
+            Ok((path, bytes)) => campaign::parse(&path, &bytes).unwrap_or_else(|err| {
+                eprintln!("{}: {}", path.display(), err);
+                Vec::new()
+            }),
+            Err(err) => {
+                eprintln!("{}", err);
+                Vec::new()
+            }
+        };
+        let progress = Progress::load();
+        // Start from the first level that hasn't been completed yet.
+        let selected = (0..levels.len())
+            .find(|&i| levels[i].map.is_some() && !progress.is_completed(&levels[i]))
+            .unwrap_or(0);
+        LevelSelect {
+            levels,
+            progress,
+            selected,
+            message: None,
+        }
+    }
+
+    fn play(&mut self) -> Action {
+        let level = &self.levels[self.selected];
+        let map = match &level.map {
+            Some(map) => map,
+            None => {
+                self.message = Some(format!("Map {} not found", level.file));
+                return Action::None;
+            }
+        };
+        if !self.progress.is_unlocked(&self.levels, self.selected) {
+            self.message = Some("Complete the previous level first".to_string());
+            return Action::None;
+        }
+        let speed = map.speed.unwrap_or(Speed::Normal);
+        Action::Push(
+            Box::new(Game::level(map, speed, &level.file, level.goal)),
+            Transition::Fade,
+        )
+    }
+
+    fn status(&self, i: usize) -> String {
+        let level = &self.levels[i];
+        if level.map.is_none() {
+            "Missing".to_string()
+        } else if self.progress.is_completed(level) {
+            "Completed".to_string()
+        } else if self.progress.is_unlocked(&self.levels, i) {
+            level.goal.describe()
+        } else {
+            "Locked".to_string()
+        }
+    }
+}
+
+impl State for LevelSelect {
+    fn handle_events(
+        &mut self,
+        events: EventPollIterator,
+        bindings: &mut Bindings,
+        audio: &mut Audio,
+    ) -> Action {
+        for event in events {
+            match event {
+                Event::Quit { .. } => return Action::Quit,
+                Event::KeyDown {
+                    scancode: Some(scancode),
+                    ..
+                } => match bindings.action(scancode) {
+                    Some(InputAction::Confirm) if !self.levels.is_empty() => {
+                        let action = self.play();
+                        if let Action::Push(..) = action {
+                            audio.play(Sound::Start);
+                        }
+                        return action;
+                    }
+                    Some(InputAction::Up) if !self.levels.is_empty() => {
+                        audio.play(Sound::Navigate);
+                        if self.selected == 0 {
+                            self.selected = self.levels.len() - 1;
+                        } else {
+                            self.selected -= 1;
+                        }
+                        self.message = None;
+                    }
+                    Some(InputAction::Down) if !self.levels.is_empty() => {
+                        audio.play(Sound::Navigate);
+                        if self.selected == self.levels.len() - 1 {
+                            self.selected = 0;
+                        } else {
+                            self.selected += 1;
+                        }
+                        self.message = None;
+                    }
+                    Some(InputAction::Back) => return Action::Pop(None),
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        Action::None
+    }
+
+    fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        font: &mut Font,
+        _tiles: &Texture,
+        _logo: &Texture,
+        _bindings: &Bindings,
+    ) {
+        canvas.set_draw_color(Color::RGB(215, 227, 244));
+        canvas.clear();
+
+        font.draw(
+            canvas,
+            ((320 - font.measure("Campaign")) / 2) as i32,
+            20,
+            "Campaign",
+            Color::RGB(0, 0, 0),
+        );
+
+        if self.levels.is_empty() {
+            font.draw(canvas, 110, 50, "No levels", Color::RGB(0, 0, 0));
+        }
+
+        for (i, level) in self.levels.iter().enumerate() {
+            let color = if self.progress.is_unlocked(&self.levels, i) && level.map.is_some() {
+                Color::RGB(0, 0, 0)
+            } else {
+                Color::RGB(128, 128, 128)
+            };
+            let name = match &level.map {
+                Some(map) => format!("{}. {}", i + 1, map.name),
+                None => format!("{}. {}", i + 1, level.file),
+            };
+            let y = 50 + i as i32 * 10;
+            font.draw(
+                canvas,
+                if i == self.selected { 50 } else { 40 },
+                y,
+                &name,
+                color,
+            );
+            let status = self.status(i);
+            font.draw(
+                canvas,
+                280 - font.measure(&status) as i32,
+                y,
+                &status,
+                color,
+            );
+        }
+
+        if let Some(message) = &self.message {
+            font.draw(canvas, 1, 230, message, Color::RGB(0, 0, 0));
+        }
+    }
+
+    // Progress is saved by the game, so it is reloaded whenever a level ends.
+    // Finishing a level moves straight on to the next one that can be played.
+    fn resume(&mut self, signal: Option<Signal>) -> Action {
+        self.progress = Progress::load();
+        if let Some(Signal::NextLevel) = signal {
+            match (self.selected + 1..self.levels.len()).find(|&i| self.levels[i].map.is_some()) {
+                Some(next) => {
+                    self.selected = next;
+                    return self.play();
+                }
+                None => self.message = Some("Campaign complete!".to_string()),
+            }
+        }
+        Action::None
+    }
+}
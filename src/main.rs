@@ -1,63 +1,197 @@
 // SPDX-FileCopyrightText: 2019 Tuomas Siipola
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use sdl2::surface::Surface;
+use std::env;
+use std::path::PathBuf;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
 
+mod ai;
+mod assets;
+mod audio;
+mod bench;
+mod broken_maps;
+mod campaign;
+mod check_map;
+mod controls;
 mod direction;
+mod editor;
 mod font;
+mod food;
 mod game;
+mod highscore;
+mod input;
+mod level_select;
 mod map;
 mod menu;
+mod pack;
+mod paths;
+mod pause;
+mod recording;
+mod render;
+mod replay;
+mod replay_menu;
+mod simulation;
 mod snake;
+mod speed;
 mod state;
 mod tile;
+mod versus;
 
-use crate::font::Font;
+use crate::assets::{AssetError, Assets};
+use crate::audio::Audio;
+use crate::input::Bindings;
 use crate::menu::Menu;
-use crate::state::{Action, State};
+use crate::state::StateManager;
+
+const FRAME_DURATION: Duration = Duration::from_millis(16);
+
+struct Options {
+    seed: Option<u32>,
+    packs: Vec<PathBuf>,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        seed: None,
+        packs: Vec::new(),
+    };
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => {
+                let value = args.next().ok_or("--seed requires a value")?;
+                options.seed = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid seed: {}", value))?,
+                );
+            }
+            "--pack" => {
+                let value = args.next().ok_or("--pack requires a value")?;
+                options.packs.push(PathBuf::from(value));
+            }
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+    Ok(options)
+}
+
+fn asset_error(err: AssetError) -> ! {
+    eprintln!("natrix: {}", err);
+    process::exit(1);
+}
 
 fn main() {
+    if env::args().nth(1).as_deref() == Some("bench") {
+        let options = match bench::parse_args(env::args().skip(2)) {
+            Ok(options) => options,
+            Err(err) => {
+                eprintln!("natrix: {}", err);
+                eprintln!("usage: {}", bench::USAGE);
+                process::exit(1);
+            }
+        };
+        if let Err(err) = bench::run(&options) {
+            eprintln!("natrix: {}", err);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if env::args().nth(1).as_deref() == Some("check-map") {
+        let paths: Vec<String> = env::args().skip(2).collect();
+        if paths.is_empty() {
+            eprintln!("usage: {}", check_map::USAGE);
+            process::exit(1);
+        }
+        if !check_map::run(&paths) {
+            process::exit(1);
+        }
+        return;
+    }
+
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("natrix: {}", err);
+            eprintln!("usage: natrix [--seed <seed>] [--pack <dir or .tar>]...");
+            eprintln!("       {}", bench::USAGE);
+            eprintln!("       {}", check_map::USAGE);
+            process::exit(1);
+        }
+    };
+
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
 
     let scale = 2;
 
     let window = video_subsystem
-        .window("Natrix", scale * 320, scale * 240)
+        .window(
+            "Natrix",
+            scale * render::SCREEN_WIDTH,
+            scale * render::SCREEN_HEIGHT,
+        )
         .position_centered()
         .build()
         .unwrap();
 
     let mut canvas = window.into_canvas().software().build().unwrap();
-    canvas.set_scale(scale as f32, scale as f32).unwrap();
+    // A logical size rather than a plain scale, so that SDL also gives mouse
+    // positions in screen pixels.
+    canvas
+        .set_logical_size(render::SCREEN_WIDTH, render::SCREEN_HEIGHT)
+        .unwrap();
 
     let texture_creator = canvas.texture_creator();
 
-    let tiles = texture_creator
-        .create_texture_from_surface(Surface::load_bmp("data/images/tiles.bmp").unwrap())
-        .unwrap();
+    let mut assets = Assets::new(&texture_creator);
 
-    let logo = texture_creator
-        .create_texture_from_surface(Surface::load_bmp("data/images/logo.bmp").unwrap())
-        .unwrap();
+    let tiles = assets
+        .texture("images/tiles.bmp")
+        .unwrap_or_else(|err| asset_error(err));
 
-    let mut font = Font::load_bmp(&texture_creator, "data/images/NeoSans.bmp");
+    let logo = assets
+        .texture("images/logo.bmp")
+        .unwrap_or_else(|err| asset_error(err));
+
+    let mut font = assets
+        .font("images/NeoSans.bmp")
+        .unwrap_or_else(|err| asset_error(err));
+
+    let mut bindings = Bindings::load();
+
+    let mut audio = Audio::new(&sdl_context);
 
     let mut event_pump = sdl_context.event_pump().unwrap();
 
-    let mut current_state: Box<dyn State> = Box::new(Menu::new());
+    let mut state_manager = StateManager::new(Box::new(Menu::new(options.seed, options.packs)));
 
+    // Input is polled and a frame drawn every FRAME_DURATION while the states
+    // tick at their own fixed rate, so drawing never affects game speed.
+    let mut previous = Instant::now();
     loop {
-        match current_state.update(
+        let frame_start = Instant::now();
+        let elapsed = frame_start - previous;
+        previous = frame_start;
+
+        if !state_manager.update(
             event_pump.poll_iter(),
+            elapsed,
             &mut canvas,
-            &mut font,
-            &tiles,
-            &logo,
+            &mut bindings,
+            &mut audio,
         ) {
-            Action::Quit => break,
-            Action::Change(next_state) => current_state = next_state,
-            Action::None => {}
+            break;
+        }
+
+        state_manager.draw(&mut canvas, &mut font, &tiles, &logo, &bindings);
+        canvas.present();
+
+        if let Some(remaining) = FRAME_DURATION.checked_sub(frame_start.elapsed()) {
+            thread::sleep(remaining);
         }
     }
 }
@@ -1,93 +1,836 @@
 // SPDX-FileCopyrightText: 2019 Tuomas Siipola
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::{self, BufReader};
 use std::path::Path;
+use std::str::FromStr;
 
+use crate::direction::Direction;
+use crate::food::Food;
+use crate::speed::Speed;
 use crate::tile::Tile;
 
+const HEADER: &str = "natrix-map 1";
+
+pub const DEFAULT_WIDTH: usize = 32;
+pub const DEFAULT_HEIGHT: usize = 23;
+pub const MAX_WIDTH: usize = 160;
+pub const MAX_HEIGHT: usize = 115;
+pub const DEFAULT_LENGTH: u8 = 11;
+// Shorter snakes would have their tail move onto their head.
+pub const MIN_LENGTH: u8 = 3;
+pub const MAX_LENGTH: u8 = 100;
+
+// Tiles are stored column by column, i.e. tiles[x * height + y].
 #[derive(Clone)]
 pub struct Map {
     pub name: String,
-    pub tiles: [[Tile; 23]; 32],
+    pub author: Option<String>,
+    pub description: Option<String>,
+    pub difficulty: Option<u8>,
+    pub width: usize,
+    pub height: usize,
+    tiles: Vec<Tile>,
+    portals: Vec<(u8, usize, usize)>,
     pub snake_x: usize,
     pub snake_y: usize,
+    pub start_direction: Direction,
+    pub start_length: u8,
+    pub speed: Option<Speed>,
+    pub wrap: bool,
+    // How likely each kind of food is to appear.
+    pub food_weights: Vec<(Food, u32)>,
+    // Track in the music directory played instead of the default one.
+    pub music: Option<String>,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+// A problem found in a map file. Lines and columns start from 1.
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub line: usize,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.column {
+            Some(column) => write!(f, "line {}, column {}: {}", self.line, column, self.message),
+            None => write!(f, "line {}: {}", self.line, self.message),
+        }
+    }
+}
+
+fn error(line: usize, column: Option<usize>, message: String) -> Diagnostic {
+    Diagnostic {
+        severity: Severity::Error,
+        line,
+        column,
+        message,
+    }
+}
+
+fn warning(line: usize, column: Option<usize>, message: String) -> Diagnostic {
+    Diagnostic {
+        severity: Severity::Warning,
+        line,
+        column,
+        message,
+    }
 }
 
 #[derive(Debug)]
 pub enum MapError {
     Io(io::Error),
-    InvalidFormat(String),
+    Invalid(Vec<Diagnostic>),
+}
+
+impl MapError {
+    pub fn errors(&self) -> Vec<&Diagnostic> {
+        match self {
+            MapError::Io(_) => Vec::new(),
+            MapError::Invalid(diagnostics) => diagnostics
+                .iter()
+                .filter(|diagnostic| diagnostic.severity == Severity::Error)
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::Io(err) => write!(f, "{}", err),
+            MapError::Invalid(_) => {
+                let errors = self.errors();
+                write!(f, "{}", errors[0])?;
+                if errors.len() > 1 {
+                    write!(f, " (and {} more)", errors.len() - 1)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn parse_number<T: FromStr + PartialOrd + fmt::Display>(
+    key: &str,
+    value: &str,
+    min: T,
+    max: T,
+) -> Result<T, String> {
+    match value.parse() {
+        Ok(n) if n >= min && n <= max => Ok(n),
+        _ => Err(format!(
+            "{} must be a number between {} and {}",
+            key, min, max
+        )),
+    }
+}
+
+fn parse_direction(value: &str) -> Result<Direction, String> {
+    match value {
+        "up" => Ok(Direction::Up),
+        "right" => Ok(Direction::Right),
+        "down" => Ok(Direction::Down),
+        "left" => Ok(Direction::Left),
+        _ => Err(format!(
+            "invalid direction {:?}, expected up, right, down or left",
+            value
+        )),
+    }
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool, String> {
+    match value {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("{} must be on or off", key)),
+    }
+}
+
+// A list like `normal 10, bonus 2, golden 1`. Kinds that aren't listed don't
+// appear at all.
+fn parse_food_weights(value: &str) -> Result<Vec<(Food, u32)>, String> {
+    let mut weights = Vec::new();
+    for item in value.split(',') {
+        let mut parts = item.split_whitespace();
+        let (name, weight) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(name), Some(weight), None) => (name, weight),
+            _ => {
+                return Err(format!(
+                    "expected food name and weight, got {:?}",
+                    item.trim()
+                ))
+            }
+        };
+        let food = Food::from_name(name).ok_or_else(|| {
+            format!(
+                "unknown food {:?}, expected normal, bonus, golden, shrink, fast or slow",
+                name
+            )
+        })?;
+        if weights.iter().any(|(other, _)| *other == food) {
+            return Err(format!("food {:?} is listed twice", name));
+        }
+        weights.push((food, parse_number("food weight", weight, 0, 1000)?));
+    }
+    if weights.iter().all(|(_, weight)| *weight == 0) {
+        return Err("at least one food needs a weight above 0".to_string());
+    }
+    Ok(weights)
+}
+
+// How a tile is written in a map file, ignoring anything that isn't part of
+// the layout.
+fn tile_char(tile: Tile) -> char {
+    match tile {
+        Tile::Wall(_) => 'X',
+        Tile::Portal(id) => (b'a' + id) as char,
+        _ => ' ',
+    }
+}
+
+fn indentation(text: &str) -> usize {
+    text.len() - text.trim_start().len()
 }
 
 impl Map {
     pub fn new() -> Map {
         Map {
             name: "Default".to_string(),
-            tiles: [[Tile::Empty; 23]; 32],
+            author: None,
+            description: None,
+            difficulty: None,
+            width: DEFAULT_WIDTH,
+            height: DEFAULT_HEIGHT,
+            tiles: vec![Tile::Empty; DEFAULT_WIDTH * DEFAULT_HEIGHT],
+            portals: Vec::new(),
             snake_x: 5,
             snake_y: 5,
+            start_direction: Direction::Right,
+            start_length: DEFAULT_LENGTH,
+            speed: None,
+            wrap: true,
+            food_weights: vec![(Food::Normal, 1)],
+            music: None,
+        }
+    }
+
+    pub fn tile(&self, x: usize, y: usize) -> Tile {
+        self.tiles[x * self.height + y]
+    }
+
+    pub fn set_tile(&mut self, x: usize, y: usize, tile: Tile) {
+        self.tiles[x * self.height + y] = tile;
+    }
+
+    pub fn tiles(&self) -> impl Iterator<Item = Tile> + '_ {
+        self.tiles.iter().cloned()
+    }
+
+    // FNV-1a over the layout so the value stays stable between builds, unlike
+    // std's DefaultHasher. Settings that change how a map plays are only
+    // included when they differ from the defaults so that maps in the old
+    // format keep their hashes.
+    pub fn content_hash(&self) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let start = [self.snake_x as u32, self.snake_y as u32];
+        let mut settings = Vec::new();
+        if self.start_direction != Direction::Right {
+            settings.push(b'd');
+            settings.push(self.start_direction as u8);
+        }
+        if self.start_length != DEFAULT_LENGTH {
+            settings.push(b'l');
+            settings.push(self.start_length);
+        }
+        if !self.wrap {
+            settings.push(b'w');
         }
+        if self.food_weights != [(Food::Normal, 1)] {
+            settings.push(b'f');
+            for (food, weight) in &self.food_weights {
+                settings.extend(food.name().bytes());
+                settings.extend(&weight.to_le_bytes());
+            }
+        }
+        let bytes = self
+            .tiles()
+            .map(|tile| tile_char(tile) as u8)
+            .chain(start.iter().flat_map(|n| n.to_le_bytes().to_vec()))
+            .chain(settings);
+        for byte in bytes {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        hash
+    }
+
+    // Writes the map in the versioned format, leaving out settings that have
+    // their default values.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        writeln!(file, "{}", HEADER)?;
+        writeln!(file, "name = {}", self.name)?;
+        if let Some(author) = &self.author {
+            writeln!(file, "author = {}", author)?;
+        }
+        if let Some(description) = &self.description {
+            writeln!(file, "description = {}", description)?;
+        }
+        if let Some(difficulty) = self.difficulty {
+            writeln!(file, "difficulty = {}", difficulty)?;
+        }
+        writeln!(file, "width = {}", self.width)?;
+        writeln!(file, "height = {}", self.height)?;
+        if self.start_direction != Direction::Right {
+            let direction = match self.start_direction {
+                Direction::Up => "up",
+                Direction::Right => "right",
+                Direction::Down => "down",
+                Direction::Left => "left",
+            };
+            writeln!(file, "direction = {}", direction)?;
+        }
+        if self.start_length != DEFAULT_LENGTH {
+            writeln!(file, "length = {}", self.start_length)?;
+        }
+        if let Some(speed) = self.speed {
+            writeln!(file, "speed = {}", speed.name().to_lowercase())?;
+        }
+        if !self.wrap {
+            writeln!(file, "wrap = off")?;
+        }
+        if self.food_weights != [(Food::Normal, 1)] {
+            let weights: Vec<String> = self
+                .food_weights
+                .iter()
+                .map(|(food, weight)| format!("{} {}", food.name(), weight))
+                .collect();
+            writeln!(file, "food = {}", weights.join(", "))?;
+        }
+        if let Some(music) = &self.music {
+            writeln!(file, "music = {}", music)?;
+        }
+        writeln!(file)?;
+        for y in 0..self.height {
+            let row: String = (0..self.width)
+                .map(|x| {
+                    if (x, y) == (self.snake_x, self.snake_y) {
+                        '@'
+                    } else {
+                        tile_char(self.tile(x, y))
+                    }
+                })
+                .collect();
+            writeln!(file, "{}", row)?;
+        }
+        Ok(())
     }
 
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Map, MapError> {
+        Map::check(path).map(|(map, _)| map)
+    }
+
+    // Maps either start with a versioned header of `key = value` lines ended
+    // by an empty line, or are in the original format where the first line is
+    // the name and the grid is always 32x23. Besides the map, returns warnings
+    // about problems that don't stop the map from being played.
+    pub fn check<P: AsRef<Path>>(path: P) -> Result<(Map, Vec<Diagnostic>), MapError> {
         let file = File::open(path).map_err(MapError::Io)?;
-        let reader = BufReader::new(file);
-        let mut lines = reader.lines();
-        let name = match lines.next() {
-            Some(line) => line.map_err(MapError::Io)?,
-            None => return Err(MapError::InvalidFormat("name required".to_string())),
+        Map::check_reader(BufReader::new(file))
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Map, MapError> {
+        Map::check_reader(bytes).map(|(map, _)| map)
+    }
+
+    fn check_reader<R: BufRead>(reader: R) -> Result<(Map, Vec<Diagnostic>), MapError> {
+        let lines = reader
+            .lines()
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(MapError::Io)?;
+
+        let mut diagnostics = Vec::new();
+        let mut map = Map::new();
+        let versioned = lines.first().map(|line| line.trim_end()) == Some(HEADER);
+        let grid_start = if versioned {
+            let end = lines
+                .iter()
+                .position(|line| line.trim().is_empty())
+                .unwrap_or(lines.len());
+            map.name.clear();
+            for (i, line) in lines.iter().enumerate().take(end).skip(1) {
+                if let Err((column, message)) = map.parse_header_line(line) {
+                    diagnostics.push(error(i + 1, Some(column), message));
+                }
+            }
+            if map.name.is_empty() {
+                diagnostics.push(error(1, None, "header has no name".to_string()));
+            }
+            if end == lines.len() {
+                diagnostics.push(error(
+                    end + 1,
+                    None,
+                    "expected an empty line followed by the map after the header".to_string(),
+                ));
+            }
+            end + 1
+        } else {
+            map.name = lines.first().map_or("", |line| line.trim()).to_string();
+            if map.name.is_empty() {
+                diagnostics.push(error(
+                    1,
+                    None,
+                    format!(
+                        "expected the name of the map or {:?} on the first line",
+                        HEADER
+                    ),
+                ));
+            }
+            1
+        };
+
+        map.tiles = vec![Tile::Empty; map.width * map.height];
+        let grid = &lines[grid_start.min(lines.len())..];
+        map.parse_grid(grid, grid_start, versioned, &mut diagnostics);
+        if diagnostics.is_empty() {
+            map.check_layout(grid_start, &mut diagnostics);
         }
-        .trim()
-        .to_string();
 
-        if name.is_empty() {
-            return Err(MapError::InvalidFormat("empty name".to_string()));
+        diagnostics.sort_by_key(|diagnostic| (diagnostic.line, diagnostic.column));
+        if diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error)
+        {
+            return Err(MapError::Invalid(diagnostics));
         }
+        map.autotile();
+        Ok((map, diagnostics))
+    }
+
+    // On error returns the column where the problem is.
+    fn parse_header_line(&mut self, line: &str) -> Result<(), (usize, String)> {
+        let key_column = indentation(line) + 1;
+        let separator = line
+            .find('=')
+            .ok_or_else(|| (key_column, "expected key = value".to_string()))?;
+        let key = line[..separator].trim();
+        let value = line[separator + 1..].trim();
+        let value_column = separator + 1 + indentation(&line[separator + 1..]) + 1;
+        let result = match key {
+            "name" => {
+                self.name = value.to_string();
+                Ok(())
+            }
+            "author" => {
+                self.author = Some(value.to_string());
+                Ok(())
+            }
+            "description" => {
+                self.description = Some(value.to_string());
+                Ok(())
+            }
+            "difficulty" => parse_number(key, value, 1, 5).map(|n| self.difficulty = Some(n)),
+            "width" => parse_number(key, value, 1, MAX_WIDTH).map(|n| self.width = n),
+            "height" => parse_number(key, value, 1, MAX_HEIGHT).map(|n| self.height = n),
+            "direction" => parse_direction(value).map(|d| self.start_direction = d),
+            "length" => {
+                parse_number(key, value, MIN_LENGTH, MAX_LENGTH).map(|n| self.start_length = n)
+            }
+            "speed" => match Speed::from_name(value) {
+                Some(speed) => {
+                    self.speed = Some(speed);
+                    Ok(())
+                }
+                None => Err(format!(
+                    "invalid speed {:?}, expected slow, normal, fast or increasing",
+                    value
+                )),
+            },
+            "wrap" => parse_bool(key, value).map(|wrap| self.wrap = wrap),
+            "food" => parse_food_weights(value).map(|weights| self.food_weights = weights),
+            "music" if value.is_empty() || value.contains(['/', '\\']) => {
+                Err(format!("invalid music file {:?}", value))
+            }
+            "music" => {
+                self.music = Some(value.to_string());
+                Ok(())
+            }
+            _ => return Err((key_column, format!("unknown key {:?}", key))),
+        };
+        result.map_err(|message| (value_column, message))
+    }
 
-        let mut tiles = [[Tile::Empty; 23]; 32];
-        let mut snake_pos = None;
-        for (y, line) in lines.take(23).enumerate() {
-            for (x, c) in line.map_err(MapError::Io)?.chars().take(32).enumerate() {
+    // The original format silently ignores unknown characters and anything
+    // outside the grid. The versioned format requires the grid to match the
+    // size in the header exactly, but empty lines after the last row are allowed.
+    fn parse_grid(
+        &mut self,
+        rows: &[String],
+        grid_start: usize,
+        strict: bool,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        let line = |y: usize| grid_start + y + 1;
+        let mut rows = rows;
+        while rows.len() > self.height && rows[rows.len() - 1].is_empty() {
+            rows = &rows[..rows.len() - 1];
+        }
+        if strict && rows.len() > self.height {
+            diagnostics.push(error(
+                line(self.height),
+                None,
+                format!(
+                    "too many rows, height is {} but the map has {}",
+                    self.height,
+                    rows.len()
+                ),
+            ));
+        }
+        if strict && rows.len() < self.height {
+            diagnostics.push(error(
+                line(rows.len()),
+                None,
+                format!(
+                    "too few rows, height is {} but the map has {}",
+                    self.height,
+                    rows.len()
+                ),
+            ));
+        }
+
+        let mut snake_pos: Option<(usize, usize)> = None;
+        for (y, row) in rows.iter().take(self.height).enumerate() {
+            let length = row.chars().count();
+            if strict && length != self.width {
+                let kind = if length > self.width { "long" } else { "short" };
+                diagnostics.push(error(
+                    line(y),
+                    Some(length.min(self.width) + 1),
+                    format!(
+                        "row is too {}, width is {} but the row has {} characters",
+                        kind, self.width, length
+                    ),
+                ));
+            }
+            for (x, c) in row.chars().take(self.width).enumerate() {
                 match c {
-                    'X' => tiles[x][y] = Tile::Wall(0),
-                    ' ' => tiles[x][y] = Tile::Empty,
-                    '@' => snake_pos = Some((x, y)),
+                    'X' => self.set_tile(x, y, Tile::Wall(0)),
+                    ' ' => self.set_tile(x, y, Tile::Empty),
+                    'a'..='z' if strict => {
+                        let id = c as u8 - b'a';
+                        self.set_tile(x, y, Tile::Portal(id));
+                        self.portals.push((id, x, y));
+                    }
+                    '@' => match snake_pos {
+                        Some((first_x, first_y)) if strict => diagnostics.push(error(
+                            line(y),
+                            Some(x + 1),
+                            format!(
+                                "more than one start (@), the first is on line {}, column {}",
+                                line(first_y),
+                                first_x + 1
+                            ),
+                        )),
+                        _ => snake_pos = Some((x, y)),
+                    },
+                    _ if strict => diagnostics.push(error(
+                        line(y),
+                        Some(x + 1),
+                        format!(
+                            "unknown character {:?}, expected X, space, @ or a portal letter",
+                            c
+                        ),
+                    )),
                     _ => {}
                 }
             }
         }
-        let (snake_x, snake_y) =
-            snake_pos.ok_or_else(|| MapError::InvalidFormat("no snake".to_string()))?;
 
-        for x in 0..32 {
-            for y in 0..23 {
-                if let Tile::Wall(i) = tiles[x][y] {
-                    let mut new_i = i;
-                    if y == 0 || tiles[x][y - 1].is_wall() {
-                        new_i += 1;
-                    }
-                    if x == 31 || tiles[x + 1][y].is_wall() {
-                        new_i += 2;
-                    }
-                    if y == 22 || tiles[x][y + 1].is_wall() {
-                        new_i += 4;
+        for id in 0..26 {
+            let tiles: Vec<_> = self
+                .portals
+                .iter()
+                .filter(|portal| portal.0 == id)
+                .collect();
+            if let Some(&&(_, x, y)) = tiles.first() {
+                if tiles.len() != 2 {
+                    diagnostics.push(error(
+                        line(y),
+                        Some(x + 1),
+                        format!(
+                            "portal {:?} needs exactly two tiles but has {}",
+                            tile_char(Tile::Portal(id)),
+                            tiles.len()
+                        ),
+                    ));
+                }
+            }
+        }
+
+        match snake_pos {
+            Some((x, y)) => {
+                self.snake_x = x;
+                self.snake_y = y;
+            }
+            None => diagnostics.push(error(line(0), None, "no start (@) in the map".to_string())),
+        }
+    }
+
+    // The tile next to the given one, or None past the edge of a map that
+    // doesn't wrap.
+    fn neighbor(&self, x: usize, y: usize, direction: Direction) -> Option<(usize, usize)> {
+        let (width, height) = (self.width, self.height);
+        let at_edge = match direction {
+            Direction::Up => y == 0,
+            Direction::Right => x == width - 1,
+            Direction::Down => y == height - 1,
+            Direction::Left => x == 0,
+        };
+        if at_edge && !self.wrap {
+            return None;
+        }
+        Some(match direction {
+            Direction::Up => (x, (y + height - 1) % height),
+            Direction::Right => ((x + 1) % width, y),
+            Direction::Down => (x, (y + 1) % height),
+            Direction::Left => ((x + width - 1) % width, y),
+        })
+    }
+
+    // Where moving one tile from (x, y) ends up. Moving into a portal continues
+    // from the tile past the other portal of the pair in the same direction.
+    // None if the move would leave a map that doesn't wrap or goes around a
+    // loop of portals.
+    pub fn step(&self, x: usize, y: usize, direction: Direction) -> Option<(usize, usize)> {
+        let mut position = self.neighbor(x, y, direction)?;
+        for _ in 0..=self.portals.len() {
+            let id = match self.tile(position.0, position.1) {
+                Tile::Portal(id) => id,
+                _ => return Some(position),
+            };
+            let &(_, pair_x, pair_y) = self
+                .portals
+                .iter()
+                .find(|&&(other, px, py)| other == id && (px, py) != position)?;
+            position = self.neighbor(pair_x, pair_y, direction)?;
+        }
+        None
+    }
+
+    // Marks every tile reachable from the start without crossing walls and
+    // returns how many tiles were marked.
+    fn fill(&self, visited: &mut [bool], start: (usize, usize)) -> usize {
+        let directions = [
+            Direction::Up,
+            Direction::Right,
+            Direction::Down,
+            Direction::Left,
+        ];
+        let mut stack = vec![start];
+        let mut count = 0;
+        visited[start.0 * self.height + start.1] = true;
+        while let Some((x, y)) = stack.pop() {
+            count += 1;
+            for direction in directions.iter() {
+                if let Some((nx, ny)) = self.step(x, y, *direction) {
+                    if !visited[nx * self.height + ny] && !self.tile(nx, ny).is_wall() {
+                        visited[nx * self.height + ny] = true;
+                        stack.push((nx, ny));
                     }
-                    if x == 0 || tiles[x - 1][y].is_wall() {
-                        new_i += 8;
+                }
+            }
+        }
+        count
+    }
+
+    // Problems with a map that parsed fine. Food can appear in areas the
+    // snake can't reach, which is allowed but probably not intended.
+    pub fn check_layout(&self, grid_start: usize, diagnostics: &mut Vec<Diagnostic>) {
+        let (x, y) = (self.snake_x, self.snake_y);
+        let blocked = match self.step(x, y, self.start_direction) {
+            Some((ahead_x, ahead_y)) => self.tile(ahead_x, ahead_y).is_wall(),
+            None => true,
+        };
+        if blocked {
+            diagnostics.push(error(
+                grid_start + y + 1,
+                Some(x + 1),
+                "the snake starts facing a wall".to_string(),
+            ));
+        }
+
+        let mut visited = vec![false; self.width * self.height];
+        self.fill(&mut visited, (x, y));
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let tile = self.tile(x, y);
+                if !visited[x * self.height + y] && !tile.is_wall() && !tile.is_portal() {
+                    let size = self.fill(&mut visited, (x, y));
+                    diagnostics.push(warning(
+                        grid_start + y + 1,
+                        Some(x + 1),
+                        format!(
+                            "area of {} tile{} can't be reached from the start",
+                            size,
+                            if size == 1 { "" } else { "s" }
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+
+    // Picks the wall sprite for each wall based on which neighbors are walls.
+    // Past the edge of a wrapping map is the other side of the map, otherwise
+    // it counts as wall. Needs to be called again after walls are changed.
+    pub fn autotile(&mut self) {
+        let directions = [
+            (Direction::Up, 1),
+            (Direction::Right, 2),
+            (Direction::Down, 4),
+            (Direction::Left, 8),
+        ];
+        for x in 0..self.width {
+            for y in 0..self.height {
+                if self.tile(x, y).is_wall() {
+                    let mut i = 0;
+                    for (direction, bit) in directions.iter() {
+                        let wall = match self.neighbor(x, y, *direction) {
+                            Some((nx, ny)) => self.tile(nx, ny).is_wall(),
+                            None => true,
+                        };
+                        if wall {
+                            i += bit;
+                        }
                     }
-                    tiles[x][y] = Tile::Wall(new_i);
+                    self.set_tile(x, y, Tile::Wall(i));
                 }
             }
         }
+    }
 
-        Ok(Map {
-            name,
-            tiles,
-            snake_x,
-            snake_y,
-        })
+    pub fn set_wrap(&mut self, wrap: bool) {
+        self.wrap = wrap;
+        self.autotile();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(text: &str) -> Result<(Map, Vec<Diagnostic>), MapError> {
+        Map::check_reader(text.as_bytes())
+    }
+
+    fn errors(text: &str) -> Vec<String> {
+        match check(text) {
+            Err(err) => err.errors().iter().map(|error| error.to_string()).collect(),
+            Ok(_) => panic!("map should not load"),
+        }
+    }
+
+    fn with_length(length: u32) -> String {
+        format!(
+            "natrix-map 1\nname = Test\nwidth = 4\nheight = 1\nlength = {}\n\n@   \n",
+            length
+        )
+    }
+
+    #[test]
+    fn old_format() {
+        let mut text = "Old map\n".to_string();
+        for y in 0..DEFAULT_HEIGHT {
+            text.push_str(if y == 3 { "X  @" } else { "X" });
+            text.push('\n');
+        }
+        let (map, diagnostics) = check(&text).unwrap();
+        assert!(diagnostics.is_empty());
+        assert_eq!(map.name, "Old map");
+        assert_eq!((map.width, map.height), (DEFAULT_WIDTH, DEFAULT_HEIGHT));
+        assert_eq!((map.snake_x, map.snake_y), (3, 3));
+        assert!(map.tile(0, 5).is_wall());
+    }
+
+    #[test]
+    fn header_errors() {
+        let text = "natrix-map 1\nwidth = 4\n  colour = red\nheight = x\n\n@   \n";
+        assert_eq!(
+            errors(text),
+            [
+                "line 1: header has no name",
+                "line 3, column 3: unknown key \"colour\"",
+                "line 4, column 10: height must be a number between 1 and 115",
+                "line 7: too few rows, height is 23 but the map has 1",
+            ]
+        );
+        assert_eq!(
+            errors("natrix-map 1\nname = Test\n")[0],
+            "line 3: expected an empty line followed by the map after the header"
+        );
+    }
+
+    #[test]
+    fn grid_errors() {
+        let header = "natrix-map 1\nname = Test\nwidth = 4\nheight = 3\n\n";
+        assert_eq!(
+            errors(&format!("{}@  \nX?X \n", header)),
+            [
+                "line 6, column 4: row is too short, width is 4 but the row has 3 characters",
+                "line 7, column 2: unknown character '?', expected X, space, @ or a portal letter",
+                "line 8: too few rows, height is 3 but the map has 2",
+            ]
+        );
+        assert_eq!(
+            errors(&format!("{}@ a \n  @ \n    \n", header)),
+            [
+                "line 6, column 3: portal 'a' needs exactly two tiles but has 1",
+                "line 7, column 3: more than one start (@), the first is on line 6, column 1",
+            ]
+        );
+        assert_eq!(
+            errors(&format!("{}    \n    \n    \n", header)),
+            ["line 6: no start (@) in the map"]
+        );
+    }
+
+    #[test]
+    fn layout_problems() {
+        let header = "natrix-map 1\nname = Test\nwidth = 4\nheight = 3\n\n";
+        assert_eq!(
+            errors(&format!("{}@X  \nXX  \n    \n", header)),
+            ["line 6, column 1: the snake starts facing a wall"]
+        );
+        let header = header.replace("\n\n", "\nwrap = off\n\n");
+        let (_, diagnostics) = check(&format!("{}@ X \nXXX \nXXXX\n", header)).unwrap();
+        let warnings: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            warnings,
+            ["line 7, column 4: area of 2 tiles can't be reached from the start"]
+        );
+        assert!(diagnostics[0].severity == Severity::Warning);
+    }
+
+    #[test]
+    fn length_bounds() {
+        let message = "line 5, column 10: length must be a number between 3 and 100";
+        assert_eq!(errors(&with_length(0)), [message]);
+        assert_eq!(errors(&with_length(2)), [message]);
+        assert_eq!(errors(&with_length(101)), [message]);
+        assert_eq!(check(&with_length(3)).unwrap().0.start_length, 3);
+        assert_eq!(check(&with_length(100)).unwrap().0.start_length, 100);
     }
 }
@@ -1,118 +1,586 @@
 // SPDX-FileCopyrightText: 2019 Tuomas Siipola
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+use std::collections::HashSet;
+use std::ffi::OsString;
 use std::fs;
-use std::thread;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
 
 use sdl2::event::{Event, EventPollIterator};
-use sdl2::keyboard::Scancode;
 use sdl2::pixels::Color;
 use sdl2::rect::Rect;
 use sdl2::render::{Canvas, Texture};
 use sdl2::video::Window;
 
+use crate::ai::Difficulty;
+use crate::assets;
+use crate::audio::{Audio, Sound};
+use crate::broken_maps::{BrokenMap, BrokenMaps};
+use crate::controls::Controls;
+use crate::editor::Editor;
 use crate::font::Font;
 use crate::game::Game;
+use crate::highscore::HighScores;
+use crate::input::{Bindings, InputAction};
+use crate::level_select::LevelSelect;
 use crate::map::Map;
-use crate::state::{Action, State};
+use crate::pack::Pack;
+use crate::paths;
+use crate::render;
+use crate::replay_menu::ReplayMenu;
+use crate::speed::Speed;
+use crate::state::{Action, Signal, State, Transition};
+use crate::versus::Versus;
+
+// The last entry is only shown when some maps failed to load.
+const ENTRIES: [&str; 9] = [
+    "Campaign",
+    "Mode",
+    "Speed",
+    "Borders",
+    "Sort",
+    "Replays",
+    "Options",
+    "Editor",
+    "Broken maps",
+];
+
+#[derive(Copy, Clone, PartialEq)]
+enum Mode {
+    Single,
+    Versus,
+    Computer(Difficulty),
+}
+
+const MODES: [Mode; 5] = [
+    Mode::Single,
+    Mode::Versus,
+    Mode::Computer(Difficulty::Easy),
+    Mode::Computer(Difficulty::Normal),
+    Mode::Computer(Difficulty::Hard),
+];
+
+// None uses the speed set by the map, or Normal if it doesn't set one.
+const SPEED_CHOICES: [Option<Speed>; 5] = [
+    None,
+    Some(Speed::Slow),
+    Some(Speed::Normal),
+    Some(Speed::Fast),
+    Some(Speed::Increasing),
+];
+
+fn speed_name(speed: Option<Speed>) -> &'static str {
+    speed.map_or("Map default", Speed::name)
+}
+
+// None uses the map's setting, otherwise whether the edges wrap around.
+const BORDER_CHOICES: [Option<bool>; 3] = [None, Some(true), Some(false)];
+
+fn border_name(wrap: Option<bool>) -> &'static str {
+    match wrap {
+        None => "Map default",
+        Some(true) => "Wrap",
+        Some(false) => "Solid",
+    }
+}
+
+// Maps are only sorted within their group so the headings stay in place. Maps
+// that compare equal keep their pack order.
+#[derive(Copy, Clone, PartialEq)]
+enum Sort {
+    Pack,
+    Name,
+    Difficulty,
+}
+
+const SORT_CHOICES: [Sort; 3] = [Sort::Pack, Sort::Name, Sort::Difficulty];
+
+fn sort_name(sort: Sort) -> &'static str {
+    match sort {
+        Sort::Pack => "Pack order",
+        Sort::Name => "Name",
+        Sort::Difficulty => "Difficulty",
+    }
+}
+
+// Rows of maps and headings shown at once, the rest is scrolled.
+const LIST_Y: i32 = 60;
+const VISIBLE_ROWS: usize = 8;
+
+fn next_choice<T: Copy + PartialEq>(choices: &[T], current: T) -> T {
+    let i = choices
+        .iter()
+        .position(|choice| *choice == current)
+        .unwrap();
+    choices[(i + 1) % choices.len()]
+}
+
+fn previous_choice<T: Copy + PartialEq>(choices: &[T], current: T) -> T {
+    let i = choices
+        .iter()
+        .position(|choice| *choice == current)
+        .unwrap();
+    choices[(i + choices.len() - 1) % choices.len()]
+}
+
+impl Mode {
+    fn name(self) -> String {
+        match self {
+            Mode::Single => "Single player".to_string(),
+            Mode::Versus => "Versus".to_string(),
+            Mode::Computer(difficulty) => format!("Computer ({})", difficulty.name()),
+        }
+    }
+
+    fn next(self) -> Mode {
+        let i = MODES.iter().position(|mode| *mode == self).unwrap();
+        MODES[(i + 1) % MODES.len()]
+    }
+
+    fn previous(self) -> Mode {
+        let i = MODES.iter().position(|mode| *mode == self).unwrap();
+        MODES[(i + MODES.len() - 1) % MODES.len()]
+    }
+}
 
 pub struct Menu {
     maps: Vec<Map>,
-    selected_map: usize,
+    // Indices into maps in the order they are listed.
+    order: Vec<usize>,
+    headings: Vec<(usize, String)>,
+    broken_maps: Vec<BrokenMap>,
+    packs: Vec<PathBuf>,
+    selected: usize,
+    seed: Option<u32>,
+    mode: Mode,
+    speed: Option<Speed>,
+    wrap: Option<bool>,
+    sort: Sort,
+    scroll: usize,
+    high_scores: HighScores,
 }
 
-fn read_maps() -> Vec<Map> {
-    match fs::read_dir("data/maps") {
-        Ok(entries) => entries
-            .filter_map(|entry| match entry {
-                Ok(entry) => Map::load(entry.path()).ok(),
-                Err(_) => None,
-            })
-            .collect(),
-        Err(_) => Vec::new(),
+fn read_dir_maps(
+    dir: &Path,
+    seen: &mut HashSet<OsString>,
+    maps: &mut Vec<Map>,
+    broken: &mut Vec<BrokenMap>,
+) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("{}: {}", dir.display(), err);
+            return;
+        }
+    };
+    let mut entries: Vec<_> = entries.filter_map(Result::ok).collect();
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        if !seen.insert(entry.file_name()) {
+            continue;
+        }
+        let file = entry.file_name().to_string_lossy().into_owned();
+        match Map::load(entry.path()) {
+            Ok(map) => maps.push(map),
+            Err(err) => {
+                eprintln!("{}: {}", entry.path().display(), err);
+                broken.push(BrokenMap::new(&file, &err));
+            }
+        }
     }
 }
 
+// Maps are grouped by where they come from: the maps that come with the game,
+// the player's own maps and then each map pack. Headings are given as the
+// index of the first map in the group. Maps in a directory are in file name
+// order. Among the maps that come with the game
+// a map in an earlier data directory hides one with the same file name in the
+// later ones, and maps built into the executable come last.
+fn read_maps(packs: &[PathBuf]) -> (Vec<Map>, Vec<(usize, String)>, Vec<BrokenMap>) {
+    let mut maps = Vec::new();
+    let mut headings = Vec::new();
+    let mut broken = Vec::new();
+
+    let user_dir = paths::user_maps_dir();
+    let mut seen = HashSet::new();
+    for dir in assets::find_dirs("maps") {
+        if Some(&dir) != user_dir.as_ref() {
+            read_dir_maps(&dir, &mut seen, &mut maps, &mut broken);
+        }
+    }
+    for (file, bytes) in assets::embedded_files("maps") {
+        if seen.insert(file.into()) {
+            match Map::parse(bytes) {
+                Ok(map) => maps.push(map),
+                Err(err) => broken.push(BrokenMap::new(file, &err)),
+            }
+        }
+    }
+    if !maps.is_empty() {
+        headings.push((0, "Natrix".to_string()));
+    }
+
+    if let Some(dir) = user_dir.filter(|dir| dir.is_dir()) {
+        let start = maps.len();
+        read_dir_maps(&dir, &mut HashSet::new(), &mut maps, &mut broken);
+        if maps.len() > start {
+            headings.push((start, "My maps".to_string()));
+        }
+    }
+
+    for path in packs {
+        match Pack::load(path) {
+            Ok(pack) => {
+                if !pack.maps.is_empty() {
+                    let heading = match &pack.author {
+                        Some(author) => format!("{} by {}", pack.name, author),
+                        None => pack.name.clone(),
+                    };
+                    headings.push((maps.len(), heading));
+                }
+                maps.extend(pack.maps);
+                broken.extend(pack.broken);
+            }
+            Err(err) => {
+                eprintln!("{}: {}", path.display(), err);
+                broken.push(BrokenMap {
+                    file: path.display().to_string(),
+                    errors: vec![err],
+                });
+            }
+        }
+    }
+
+    if maps.is_empty() {
+        maps.push(Map::new());
+    }
+    // A heading isn't needed when all maps come from the same place.
+    if headings.len() == 1 {
+        headings.clear();
+    }
+    (maps, headings, broken)
+}
+
 impl Menu {
-    pub fn new() -> Menu {
-        let maps = read_maps();
-        Menu {
-            selected_map: 0,
-            maps: if maps.is_empty() {
-                vec![Map::new()]
-            } else {
-                maps
-            },
+    pub fn new(seed: Option<u32>, packs: Vec<PathBuf>) -> Menu {
+        let (maps, headings, broken_maps) = read_maps(&packs);
+        let mut menu = Menu {
+            order: Vec::new(),
+            headings,
+            broken_maps,
+            packs,
+            selected: 0,
+            seed,
+            mode: Mode::Single,
+            speed: None,
+            wrap: None,
+            sort: Sort::Pack,
+            scroll: 0,
+            high_scores: HighScores::load(),
+            maps,
+        };
+        menu.sort_maps();
+        menu
+    }
+
+    fn sort_maps(&mut self) {
+        let mut bounds = vec![0];
+        bounds.extend(self.headings.iter().map(|(start, _)| *start));
+        bounds.push(self.maps.len());
+        bounds.dedup();
+        self.order = (0..self.maps.len()).collect();
+        let maps = &self.maps;
+        for group in bounds.windows(2) {
+            let group = &mut self.order[group[0]..group[1]];
+            match self.sort {
+                Sort::Pack => {}
+                Sort::Name => group.sort_by_key(|i| maps[*i].name.to_lowercase()),
+                // Maps without a difficulty go last.
+                Sort::Difficulty => group.sort_by_key(|i| maps[*i].difficulty.unwrap_or(u8::MAX)),
+            }
+        }
+    }
+
+    fn selected_map(&self) -> Option<&Map> {
+        self.order.get(self.selected).map(|i| &self.maps[*i])
+    }
+
+    fn row_count(&self) -> usize {
+        self.maps.len() + self.headings.len()
+    }
+
+    // Screen row of a map, counting the headings above it.
+    fn map_row(&self, i: usize) -> usize {
+        i + self
+            .headings
+            .iter()
+            .filter(|(start, _)| *start <= i)
+            .count()
+    }
+
+    // Scrolls just enough to show the selected map, along with the heading of
+    // its group when it's the first map in it.
+    fn scroll_to_selected(&mut self) {
+        if self.selected >= self.maps.len() {
+            return;
+        }
+        let row = self.map_row(self.selected);
+        let top = if self
+            .headings
+            .iter()
+            .any(|(start, _)| *start == self.selected)
+        {
+            row - 1
+        } else {
+            row
+        };
+        if top < self.scroll {
+            self.scroll = top;
+        } else if row >= self.scroll + VISIBLE_ROWS {
+            self.scroll = row + 1 - VISIBLE_ROWS;
+        }
+    }
+
+    fn entry_count(&self) -> usize {
+        if self.broken_maps.is_empty() {
+            ENTRIES.len() - 1
+        } else {
+            ENTRIES.len()
+        }
+    }
+
+    fn reload_maps(&mut self) {
+        let entry = self.selected.checked_sub(self.maps.len());
+        let (maps, headings, broken_maps) = read_maps(&self.packs);
+        self.maps = maps;
+        self.headings = headings;
+        self.broken_maps = broken_maps;
+        self.sort_maps();
+        self.selected = match entry {
+            Some(i) => self.maps.len() + i.min(self.entry_count() - 1),
+            None => self.selected.min(self.maps.len() - 1),
+        };
+        self.scroll = self
+            .scroll
+            .min(self.row_count().saturating_sub(VISIBLE_ROWS));
+        self.scroll_to_selected();
+    }
+
+    fn start_game(&self) -> Box<dyn State> {
+        let mut map = self.selected_map().unwrap().clone();
+        if let Some(wrap) = self.wrap {
+            map.set_wrap(wrap);
+        }
+        let speed = self.speed.or(map.speed).unwrap_or(Speed::Normal);
+        match self.mode {
+            Mode::Single => Box::new(Game::new(&map, self.seed, speed)),
+            Mode::Versus => Box::new(Versus::new(&map, speed, None)),
+            Mode::Computer(difficulty) => Box::new(Versus::new(&map, speed, Some(difficulty))),
         }
     }
 }
 
 impl State for Menu {
-    fn update(
+    fn handle_events(
         &mut self,
         events: EventPollIterator,
-        canvas: &mut Canvas<Window>,
-        font: &mut Font,
-        tiles: &Texture,
-        logo: &Texture,
+        bindings: &mut Bindings,
+        audio: &mut Audio,
     ) -> Action {
+        let count = self.maps.len() + self.entry_count();
         for event in events {
             match event {
                 Event::Quit { .. } => return Action::Quit,
                 Event::KeyDown {
                     scancode: Some(scancode),
                     ..
-                } => match scancode {
-                    Scancode::Space => {
-                        return Action::Change(Box::new(Game::new(
-                            canvas,
-                            font,
-                            tiles,
-                            &self.maps[self.selected_map],
-                        )));
+                } => match bindings.action(scancode) {
+                    Some(InputAction::Confirm) => {
+                        return match self.selected.checked_sub(self.maps.len()) {
+                            None => {
+                                audio.play(Sound::Start);
+                                Action::Push(self.start_game(), Transition::Fade)
+                            }
+                            Some(0) => Action::Push(Box::new(LevelSelect::new()), Transition::None),
+                            Some(1) => {
+                                self.mode = self.mode.next();
+                                Action::None
+                            }
+                            Some(2) => {
+                                self.speed = next_choice(&SPEED_CHOICES, self.speed);
+                                Action::None
+                            }
+                            Some(3) => {
+                                self.wrap = next_choice(&BORDER_CHOICES, self.wrap);
+                                Action::None
+                            }
+                            Some(4) => {
+                                self.sort = next_choice(&SORT_CHOICES, self.sort);
+                                self.sort_maps();
+                                Action::None
+                            }
+                            Some(5) => Action::Push(
+                                Box::new(ReplayMenu::new(self.maps.clone())),
+                                Transition::None,
+                            ),
+                            Some(6) => {
+                                Action::Push(Box::new(Controls::new(audio)), Transition::None)
+                            }
+                            Some(7) => Action::Push(Box::new(Editor::new()), Transition::Fade),
+                            Some(_) => Action::Push(
+                                Box::new(BrokenMaps::new(self.broken_maps.clone())),
+                                Transition::None,
+                            ),
+                        };
                     }
-                    Scancode::W => {
-                        if self.selected_map == 0 {
-                            self.selected_map = self.maps.len() - 1;
+                    Some(InputAction::Up) => {
+                        audio.play(Sound::Navigate);
+                        if self.selected == 0 {
+                            self.selected = count - 1;
                         } else {
-                            self.selected_map -= 1;
+                            self.selected -= 1;
                         }
+                        self.scroll_to_selected();
                     }
-                    Scancode::S => {
-                        if self.selected_map == self.maps.len() - 1 {
-                            self.selected_map = 0;
+                    Some(InputAction::Down) => {
+                        audio.play(Sound::Navigate);
+                        if self.selected == count - 1 {
+                            self.selected = 0;
                         } else {
-                            self.selected_map += 1;
+                            self.selected += 1;
+                        }
+                        self.scroll_to_selected();
+                    }
+                    Some(InputAction::Left) => {
+                        match self.selected.checked_sub(self.maps.len()) {
+                            Some(1) => self.mode = self.mode.previous(),
+                            Some(2) => self.speed = previous_choice(&SPEED_CHOICES, self.speed),
+                            Some(3) => self.wrap = previous_choice(&BORDER_CHOICES, self.wrap),
+                            Some(4) => {
+                                self.sort = previous_choice(&SORT_CHOICES, self.sort);
+                                self.sort_maps();
+                            }
+                            _ => continue,
                         }
+                        audio.play(Sound::Navigate);
+                    }
+                    Some(InputAction::Right) => {
+                        match self.selected.checked_sub(self.maps.len()) {
+                            Some(1) => self.mode = self.mode.next(),
+                            Some(2) => self.speed = next_choice(&SPEED_CHOICES, self.speed),
+                            Some(3) => self.wrap = next_choice(&BORDER_CHOICES, self.wrap),
+                            Some(4) => {
+                                self.sort = next_choice(&SORT_CHOICES, self.sort);
+                                self.sort_maps();
+                            }
+                            _ => continue,
+                        }
+                        audio.play(Sound::Navigate);
                     }
                     _ => {}
                 },
                 _ => {}
             }
         }
+        Action::None
+    }
 
+    fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        font: &mut Font,
+        _tiles: &Texture,
+        logo: &Texture,
+        _bindings: &Bindings,
+    ) {
         canvas.set_draw_color(Color::RGB(215, 227, 244));
         canvas.clear();
 
         canvas
-            .copy(logo, None, Rect::new((320 - 175) / 2, 40, 175, 40))
+            .copy(logo, None, Rect::new((320 - 175) / 2, 10, 175, 40))
             .unwrap();
 
-        for (i, map) in self.maps.iter().enumerate() {
+        let visible = self.scroll..self.scroll + VISIBLE_ROWS;
+        let row_y = |row: usize| LIST_Y + (row - self.scroll) as i32 * 10;
+
+        for (i, (start, heading)) in self.headings.iter().enumerate() {
+            if visible.contains(&(start + i)) {
+                font.draw(
+                    canvas,
+                    100,
+                    row_y(start + i),
+                    heading,
+                    Color::RGB(80, 80, 80),
+                );
+            }
+        }
+
+        for (i, map) in self.order.iter().map(|i| &self.maps[*i]).enumerate() {
+            let row = self.map_row(i);
+            if !visible.contains(&row) {
+                continue;
+            }
             font.draw(
                 canvas,
-                if i == self.selected_map { 120 } else { 110 },
-                110 + i as i32 * 10,
+                if i == self.selected { 120 } else { 110 },
+                row_y(row),
                 &map.name,
                 Color::RGB(0, 0, 0),
             );
+            if let Some(score) = self.high_scores.best(map) {
+                let text = score.to_string();
+                font.draw(
+                    canvas,
+                    230 - font.measure(&text) as i32,
+                    row_y(row),
+                    &text,
+                    Color::RGB(0, 0, 0),
+                );
+            }
         }
 
-        canvas.present();
+        let rows = self.row_count();
+        let list_height = VISIBLE_ROWS as u32 * 10;
+        render::draw_scrollbar(
+            canvas,
+            Rect::new(234, LIST_Y, 2, list_height),
+            self.scroll,
+            VISIBLE_ROWS,
+            rows,
+        );
 
-        thread::sleep(Duration::from_millis(100));
+        if let Some(map) = self.selected_map() {
+            render::draw_preview(canvas, map, Rect::new(4, LIST_Y, 92, list_height));
+        }
+
+        for (i, entry) in ENTRIES.iter().take(self.entry_count()).enumerate() {
+            let text = match i {
+                1 => format!("{}: < {} >", entry, self.mode.name()),
+                2 => format!("{}: < {} >", entry, speed_name(self.speed)),
+                3 => format!("{}: < {} >", entry, border_name(self.wrap)),
+                4 => format!("{}: < {} >", entry, sort_name(self.sort)),
+                8 => format!("{} ({})", entry, self.broken_maps.len()),
+                _ => entry.to_string(),
+            };
+            let row = rows.min(VISIBLE_ROWS) + i;
+            let i = self.maps.len() + i;
+            font.draw(
+                canvas,
+                if i == self.selected { 120 } else { 110 },
+                LIST_Y + 5 + row as i32 * 10,
+                &text,
+                Color::RGB(0, 0, 0),
+            );
+        }
+    }
 
+    // High scores may have changed after any game, but maps only change when
+    // one is saved in the editor.
+    fn resume(&mut self, signal: Option<Signal>) -> Action {
+        if let Some(Signal::MapsChanged) = signal {
+            self.reload_maps();
+        }
+        self.high_scores = HighScores::load();
         Action::None
     }
 }
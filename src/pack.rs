@@ -0,0 +1,185 @@
+// SPDX-FileCopyrightText: 2019 Tuomas Siipola
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::fs;
+use std::path::Path;
+
+use crate::broken_maps::BrokenMap;
+use crate::map::Map;
+
+const HEADER: &str = "natrix-pack 1";
+const MANIFEST: &str = "manifest";
+
+// A map pack is a directory or an uncompressed tar archive with a manifest
+// and the map files next to it. The manifest looks like
+//
+//     natrix-pack 1
+//     name = Pack name
+//     author = Someone
+//     order = first.map, second.map
+//
+// where author and order are optional. Maps left out of the order come after
+// the listed ones sorted by file name.
+pub struct Pack {
+    pub name: String,
+    pub author: Option<String>,
+    pub maps: Vec<Map>,
+    pub broken: Vec<BrokenMap>,
+}
+
+fn field(header: &[u8], start: usize, end: usize) -> &[u8] {
+    let field = &header[start..end];
+    let length = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+    &field[..length]
+}
+
+fn parse_octal(field: &[u8]) -> Option<usize> {
+    let text = String::from_utf8_lossy(field);
+    let text = text.trim_matches(|c: char| c == ' ' || c == '\0');
+    if text.is_empty() {
+        return Some(0);
+    }
+    usize::from_str_radix(text, 8).ok()
+}
+
+// Only regular files are returned. Other entries, like directories and the
+// extended headers some tools write, are skipped.
+fn read_tar(bytes: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut files = Vec::new();
+    let mut offset = 0;
+    while offset + 512 <= bytes.len() {
+        let header = &bytes[offset..offset + 512];
+        if header.iter().all(|b| *b == 0) {
+            break;
+        }
+        let size = parse_octal(field(header, 124, 136))
+            .ok_or_else(|| format!("invalid size in tar header at byte {}", offset))?;
+        let start = offset + 512;
+        let data = bytes
+            .get(start..start + size)
+            .ok_or("tar archive is truncated")?;
+        if header[156] == b'0' || header[156] == 0 {
+            let mut name = String::from_utf8_lossy(field(header, 0, 100)).into_owned();
+            if &header[257..263] == b"ustar\0" {
+                let prefix = String::from_utf8_lossy(field(header, 345, 500));
+                if !prefix.is_empty() {
+                    name = format!("{}/{}", prefix, name);
+                }
+            }
+            files.push((name, data.to_vec()));
+        }
+        offset = start + size.div_ceil(512) * 512;
+    }
+    Ok(files)
+}
+
+// Files directly in the directory of the manifest, by file name.
+fn archive_files(path: &Path) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let files = read_tar(&fs::read(path).map_err(|err| err.to_string())?)?;
+    let root = files
+        .iter()
+        .map(|(name, _)| name.trim_start_matches("./"))
+        .filter_map(|name| name.strip_suffix(MANIFEST))
+        .filter(|root| root.is_empty() || root.ends_with('/'))
+        .min_by_key(|root| root.len())
+        .ok_or("no manifest in archive")?
+        .to_string();
+    Ok(files
+        .into_iter()
+        .filter_map(|(name, bytes)| {
+            let name = name.trim_start_matches("./").strip_prefix(&root)?;
+            if name.contains('/') {
+                None
+            } else {
+                Some((name.to_string(), bytes))
+            }
+        })
+        .collect())
+}
+
+fn directory_files(path: &Path) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(path).map_err(|err| err.to_string())? {
+        let entry = entry.map_err(|err| err.to_string())?;
+        if entry.path().is_file() {
+            let bytes = fs::read(entry.path())
+                .map_err(|err| format!("{}: {}", entry.path().display(), err))?;
+            files.push((entry.file_name().to_string_lossy().into_owned(), bytes));
+        }
+    }
+    Ok(files)
+}
+
+impl Pack {
+    pub fn load(path: &Path) -> Result<Pack, String> {
+        let mut files = if path.is_dir() {
+            directory_files(path)?
+        } else {
+            archive_files(path)?
+        };
+        let manifest = files
+            .iter()
+            .find(|(name, _)| name == MANIFEST)
+            .ok_or("no manifest")?;
+        let manifest = String::from_utf8_lossy(&manifest.1).into_owned();
+
+        let mut lines = manifest.lines();
+        if lines.next().map(str::trim_end) != Some(HEADER) {
+            return Err(format!("{}: not a map pack manifest", MANIFEST));
+        }
+        let mut name = None;
+        let mut author = None;
+        let mut order = Vec::new();
+        for (i, line) in lines.enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, '=').map(str::trim);
+            match (parts.next(), parts.next()) {
+                (Some("name"), Some(value)) if !value.is_empty() => name = Some(value.to_string()),
+                (Some("author"), Some(value)) => author = Some(value.to_string()),
+                (Some("order"), Some(value)) => {
+                    order = value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|file| !file.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                }
+                _ => return Err(format!("{}:{}: invalid line", MANIFEST, i + 2)),
+            }
+        }
+        let name = name.ok_or_else(|| format!("{}: no name", MANIFEST))?;
+
+        files.retain(|(file, _)| file.ends_with(".map"));
+        files.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for file in order.iter().rev() {
+            match files.iter().position(|(name, _)| name == file) {
+                Some(i) => {
+                    let entry = files.remove(i);
+                    files.insert(0, entry);
+                }
+                None => eprintln!("{}: {} is not in the pack", path.display(), file),
+            }
+        }
+
+        let mut maps = Vec::new();
+        let mut broken = Vec::new();
+        for (file, bytes) in files {
+            match Map::parse(&bytes) {
+                Ok(map) => maps.push(map),
+                Err(err) => {
+                    eprintln!("{}: {}: {}", path.display(), file, err);
+                    broken.push(BrokenMap::new(&format!("{}/{}", name, file), &err));
+                }
+            }
+        }
+        Ok(Pack {
+            name,
+            author,
+            maps,
+            broken,
+        })
+    }
+}
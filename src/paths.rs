@@ -0,0 +1,54 @@
+// SPDX-FileCopyrightText: 2019 Tuomas Siipola
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+pub fn config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|path| path.join("natrix"))
+}
+
+pub fn data_dir() -> Option<PathBuf> {
+    env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .map(|path| path.join("natrix"))
+}
+
+// Maps made in the editor are saved here.
+pub fn user_maps_dir() -> Option<PathBuf> {
+    data_dir().map(|path| path.join("maps"))
+}
+
+// Directories searched for game data, most important first: the directory in
+// NATRIX_DATA_DIR, data next to the executable, the system XDG data
+// directories and finally the source tree the game was built from. The
+// player's own data directory isn't one of them: the game writes progress and
+// maps there, which must not be taken for the files that come with the game.
+pub fn asset_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(dir) = env::var_os("NATRIX_DATA_DIR") {
+        dirs.push(PathBuf::from(dir));
+    }
+    if let Some(dir) = env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join("data")))
+    {
+        dirs.push(dir);
+    }
+    let data_dirs = env::var_os("XDG_DATA_DIRS")
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".into());
+    dirs.extend(
+        env::split_paths(&data_dirs)
+            .filter(|path| path.is_absolute())
+            .map(|path| path.join("natrix")),
+    );
+    dirs.push(Path::new(env!("CARGO_MANIFEST_DIR")).join("data"));
+    dirs
+}
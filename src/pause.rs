@@ -0,0 +1,119 @@
+// SPDX-FileCopyrightText: 2019 Tuomas Siipola
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use sdl2::event::{Event, EventPollIterator};
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::Window;
+
+use crate::audio::{self, Audio, Sound};
+use crate::controls::Controls;
+use crate::font::Font;
+use crate::input::{Bindings, InputAction};
+use crate::map::Map;
+use crate::render;
+use crate::state::{Action, Signal, State, Transition};
+
+const ENTRIES: [&str; 4] = ["Resume", "Restart", "Options", "Quit to menu"];
+
+// The paused game is drawn from a snapshot of the board.
+pub struct Pause {
+    map: Map,
+    score: String,
+    status: String,
+    selected: usize,
+}
+
+impl Pause {
+    pub fn new(map: Map, score: String, status: String) -> Pause {
+        Pause {
+            map,
+            score,
+            status,
+            selected: 0,
+        }
+    }
+}
+
+impl State for Pause {
+    fn handle_events(
+        &mut self,
+        events: EventPollIterator,
+        bindings: &mut Bindings,
+        audio: &mut Audio,
+    ) -> Action {
+        for event in events {
+            match event {
+                Event::Quit { .. } => return Action::Quit,
+                Event::KeyDown {
+                    scancode: Some(scancode),
+                    ..
+                } => match bindings.action(scancode) {
+                    Some(InputAction::Confirm) => match self.selected {
+                        0 => return Action::Pop(None),
+                        1 => return Action::Pop(Some(Signal::Restart)),
+                        2 => {
+                            return Action::Push(Box::new(Controls::new(audio)), Transition::None);
+                        }
+                        _ => return Action::Pop(Some(Signal::ExitToMenu)),
+                    },
+                    Some(InputAction::Back) | Some(InputAction::Pause) => {
+                        return Action::Pop(None);
+                    }
+                    Some(InputAction::Up) => {
+                        audio.play(Sound::Navigate);
+                        if self.selected == 0 {
+                            self.selected = ENTRIES.len() - 1;
+                        } else {
+                            self.selected -= 1;
+                        }
+                    }
+                    Some(InputAction::Down) => {
+                        audio.play(Sound::Navigate);
+                        if self.selected == ENTRIES.len() - 1 {
+                            self.selected = 0;
+                        } else {
+                            self.selected += 1;
+                        }
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        Action::None
+    }
+
+    // The music of the game keeps playing while it is paused.
+    fn music(&self) -> Option<&str> {
+        Some(audio::map_music(&self.map))
+    }
+
+    fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        font: &mut Font,
+        tiles: &Texture,
+        _logo: &Texture,
+        _bindings: &Bindings,
+    ) {
+        render::draw_game(
+            canvas,
+            font,
+            tiles,
+            &self.map,
+            &self.score,
+            Some(&self.status),
+        );
+
+        let mut lines = vec!["Paused".to_string(), String::new()];
+        for (i, entry) in ENTRIES.iter().enumerate() {
+            lines.push(if i == self.selected {
+                format!("> {} <", entry)
+            } else {
+                entry.to_string()
+            });
+        }
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+        render::draw_dialog(canvas, font, &lines);
+    }
+}
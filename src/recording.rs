@@ -0,0 +1,276 @@
+// SPDX-FileCopyrightText: 2019 Tuomas Siipola
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::prelude::*;
+use std::io::{self, BufReader};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::direction::Direction;
+use crate::paths;
+use crate::speed::Speed;
+
+const HEADER: &str = "natrix-replay 1";
+// Days of play even at the fastest speed. Replays come from other players, so
+// a made-up count mustn't be able to use up all memory.
+const MAX_INPUTS: usize = 10_000_000;
+
+#[derive(Clone)]
+pub struct Recording {
+    pub map_name: String,
+    pub map_hash: u64,
+    pub seed: u32,
+    pub speed: Speed,
+    pub inputs: Vec<Direction>,
+}
+
+#[derive(Debug)]
+pub enum RecordingError {
+    Io(io::Error),
+    InvalidFormat(String),
+}
+
+impl fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordingError::Io(err) => write!(f, "{}", err),
+            RecordingError::InvalidFormat(message) => write!(f, "invalid format: {}", message),
+        }
+    }
+}
+
+fn invalid(message: &str) -> RecordingError {
+    RecordingError::InvalidFormat(message.to_string())
+}
+
+fn direction_to_char(direction: Direction) -> char {
+    match direction {
+        Direction::Up => 'U',
+        Direction::Right => 'R',
+        Direction::Down => 'D',
+        Direction::Left => 'L',
+    }
+}
+
+fn char_to_direction(c: char) -> Option<Direction> {
+    match c {
+        'U' => Some(Direction::Up),
+        'R' => Some(Direction::Right),
+        'D' => Some(Direction::Down),
+        'L' => Some(Direction::Left),
+        _ => None,
+    }
+}
+
+// Inputs are stored run-length encoded, e.g. "12R3D" for twelve ticks right
+// followed by three ticks down.
+fn encode_inputs(inputs: &[Direction]) -> String {
+    let mut result = String::new();
+    let mut iter = inputs.iter().peekable();
+    while let Some(&direction) = iter.next() {
+        let mut count = 1;
+        while iter.peek() == Some(&&direction) {
+            iter.next();
+            count += 1;
+        }
+        result.push_str(&count.to_string());
+        result.push(direction_to_char(direction));
+    }
+    result
+}
+
+fn decode_inputs(text: &str) -> Result<Vec<Direction>, RecordingError> {
+    let mut inputs = Vec::new();
+    let mut digits = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let direction = char_to_direction(c).ok_or_else(|| invalid("invalid direction"))?;
+        let count = digits
+            .parse()
+            .ok()
+            .filter(|count| *count > 0)
+            .ok_or_else(|| invalid("invalid count"))?;
+        if count > MAX_INPUTS - inputs.len() {
+            return Err(invalid("too many inputs"));
+        }
+        inputs.extend(iter::repeat_n(direction, count));
+        digits.clear();
+    }
+    if !digits.is_empty() {
+        return Err(invalid("trailing count"));
+    }
+    Ok(inputs)
+}
+
+pub fn replay_dir() -> Option<PathBuf> {
+    paths::data_dir().map(|path| path.join("replays"))
+}
+
+pub fn list_replays() -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = match replay_dir().map(fs::read_dir) {
+        Some(Ok(entries)) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension() == Some("replay".as_ref()))
+            .collect(),
+        _ => Vec::new(),
+    };
+    paths.sort();
+    paths.reverse();
+    paths
+}
+
+impl Recording {
+    pub fn new(map_name: &str, map_hash: u64, seed: u32, speed: Speed) -> Recording {
+        Recording {
+            map_name: map_name.to_string(),
+            map_hash,
+            seed,
+            speed,
+            inputs: Vec::new(),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Recording, RecordingError> {
+        let file = File::open(path).map_err(RecordingError::Io)?;
+        let mut lines = BufReader::new(file).lines();
+        let mut next_line = || match lines.next() {
+            Some(line) => line.map_err(RecordingError::Io),
+            None => Err(invalid("unexpected end of file")),
+        };
+
+        if next_line()? != HEADER {
+            return Err(invalid("not a replay file"));
+        }
+
+        let line = next_line()?;
+        let mut parts = line
+            .strip_prefix("map ")
+            .ok_or_else(|| invalid("map required"))?
+            .splitn(2, ' ');
+        let map_hash = parts
+            .next()
+            .and_then(|hash| u64::from_str_radix(hash, 16).ok())
+            .ok_or_else(|| invalid("invalid map hash"))?;
+        let map_name = parts
+            .next()
+            .ok_or_else(|| invalid("map name required"))?
+            .to_string();
+
+        let seed = next_line()?
+            .strip_prefix("seed ")
+            .and_then(|seed| seed.parse().ok())
+            .ok_or_else(|| invalid("invalid seed"))?;
+
+        // Replays saved before the speed was stored were all played at
+        // normal speed.
+        let mut line = next_line()?;
+        let speed = match line.strip_prefix("speed ") {
+            Some(name) => {
+                let speed = Speed::from_name(name).ok_or_else(|| invalid("invalid speed"))?;
+                line = next_line()?;
+                speed
+            }
+            None => Speed::Normal,
+        };
+
+        let inputs = decode_inputs(
+            line.strip_prefix("inputs ")
+                .ok_or_else(|| invalid("inputs required"))?,
+        )?;
+
+        Ok(Recording {
+            map_name,
+            map_hash,
+            seed,
+            speed,
+            inputs,
+        })
+    }
+
+    fn write(&self, mut file: File) -> io::Result<()> {
+        writeln!(file, "{}", HEADER)?;
+        writeln!(file, "map {:016x} {}", self.map_hash, self.map_name)?;
+        writeln!(file, "seed {}", self.seed)?;
+        writeln!(file, "speed {}", self.speed.name().to_lowercase())?;
+        writeln!(file, "inputs {}", encode_inputs(&self.inputs))?;
+        Ok(())
+    }
+
+    pub fn save_new(&self) -> io::Result<PathBuf> {
+        let dir = replay_dir()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no data directory"))?;
+        fs::create_dir_all(&dir)?;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        // Games saved within the same second get a counter after the time.
+        // Creating the file fails if it exists, so nothing gets overwritten.
+        let mut count = 0;
+        loop {
+            let path = dir.join(format!("{}-{:02}.replay", timestamp, count));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => {
+                    self.write(file)?;
+                    return Ok(path);
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => count += 1,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_message(text: &str) -> String {
+        match decode_inputs(text) {
+            Err(RecordingError::InvalidFormat(message)) => message,
+            _ => panic!("{:?} should not decode", text),
+        }
+    }
+
+    #[test]
+    fn inputs_round_trip() {
+        let inputs = [
+            Direction::Right,
+            Direction::Right,
+            Direction::Right,
+            Direction::Down,
+            Direction::Left,
+            Direction::Left,
+        ];
+        let text = encode_inputs(&inputs);
+        assert_eq!(text, "3R1D2L");
+        assert!(decode_inputs(&text).unwrap() == inputs);
+        assert!(decode_inputs("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn bad_counts_are_rejected() {
+        assert_eq!(error_message("R"), "invalid count");
+        assert_eq!(error_message("0R"), "invalid count");
+        assert_eq!(error_message("99999999999999999999999R"), "invalid count");
+        assert_eq!(error_message("12"), "trailing count");
+        assert_eq!(error_message("3X"), "invalid direction");
+    }
+
+    #[test]
+    fn too_many_inputs_are_rejected() {
+        assert_eq!(error_message("9999999999999999999R"), "too many inputs");
+        let text = format!("{}R1D", MAX_INPUTS);
+        assert_eq!(error_message(&text), "too many inputs");
+        assert_eq!(
+            decode_inputs(&format!("{}R", MAX_INPUTS)).unwrap().len(),
+            MAX_INPUTS
+        );
+    }
+}
@@ -0,0 +1,352 @@
+// SPDX-FileCopyrightText: 2019 Tuomas Siipola
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::Window;
+
+use crate::direction::Direction;
+use crate::font::Font;
+use crate::food::Food;
+use crate::map::Map;
+use crate::tile::Tile;
+
+pub const SCREEN_WIDTH: u32 = 320;
+pub const SCREEN_HEIGHT: u32 = 240;
+const HUD_HEIGHT: u32 = 10;
+const MAX_TILE_SIZE: u32 = 10;
+
+// Where the board goes on screen. Tiles are scaled down so that large maps
+// fit and the board is centered below the HUD.
+struct Layout {
+    x: i32,
+    y: i32,
+    tile_size: u32,
+}
+
+impl Layout {
+    fn new(map: &Map) -> Layout {
+        let board_height = SCREEN_HEIGHT - HUD_HEIGHT;
+        let tile_size = (SCREEN_WIDTH / map.width as u32)
+            .min(board_height / map.height as u32)
+            .clamp(1, MAX_TILE_SIZE);
+        Layout {
+            x: (SCREEN_WIDTH as i32 - (map.width as u32 * tile_size) as i32) / 2,
+            y: HUD_HEIGHT as i32
+                + (board_height as i32 - (map.height as u32 * tile_size) as i32) / 2,
+            tile_size,
+        }
+    }
+
+    fn tile_rect(&self, x: usize, y: usize) -> Rect {
+        Rect::new(
+            self.x + (x as u32 * self.tile_size) as i32,
+            self.y + (y as u32 * self.tile_size) as i32,
+            self.tile_size,
+            self.tile_size,
+        )
+    }
+
+    fn tile_at(&self, map: &Map, x: i32, y: i32) -> Option<(usize, usize)> {
+        if x < self.x || y < self.y {
+            return None;
+        }
+        let tile_x = ((x - self.x) / self.tile_size as i32) as usize;
+        let tile_y = ((y - self.y) / self.tile_size as i32) as usize;
+        if tile_x < map.width && tile_y < map.height {
+            Some((tile_x, tile_y))
+        } else {
+            None
+        }
+    }
+}
+
+pub fn tile_rect(map: &Map, x: usize, y: usize) -> Rect {
+    Layout::new(map).tile_rect(x, y)
+}
+
+// The tile under a point on the screen, if any.
+pub fn tile_at(map: &Map, x: i32, y: i32) -> Option<(usize, usize)> {
+    Layout::new(map).tile_at(map, x, y)
+}
+
+pub fn draw_game(
+    canvas: &mut Canvas<Window>,
+    font: &mut Font,
+    tiles: &Texture,
+    map: &Map,
+    score: &str,
+    status: Option<&str>,
+) {
+    canvas.set_draw_color(Color::RGB(255, 255, 255));
+    canvas.clear();
+
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas
+        .fill_rect(Rect::new(0, 0, SCREEN_WIDTH, HUD_HEIGHT))
+        .unwrap();
+    draw_score(canvas, font, score);
+    font.draw(
+        canvas,
+        ((SCREEN_WIDTH - font.measure(&map.name)) / 2) as i32,
+        0,
+        &map.name,
+        Color::RGB(255, 255, 255),
+    );
+    draw_status(canvas, font, status);
+
+    let layout = Layout::new(map);
+    for x in 0..map.width {
+        for y in 0..map.height {
+            draw_tile(canvas, tiles, map.tile(x, y), layout.tile_rect(x, y));
+        }
+    }
+}
+
+fn draw_score(canvas: &mut Canvas<Window>, font: &mut Font, text: &str) {
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas
+        .fill_rect(Rect::new(0, 0, font.measure(text), 10))
+        .unwrap();
+    font.draw(canvas, 1, 0, text, Color::RGB(255, 255, 255));
+}
+
+fn draw_status(canvas: &mut Canvas<Window>, font: &mut Font, text: Option<&str>) {
+    let width = text.map_or(0, |text| font.measure(text) + 2).max(120);
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas
+        .fill_rect(Rect::new(
+            SCREEN_WIDTH as i32 - width as i32,
+            0,
+            width,
+            HUD_HEIGHT,
+        ))
+        .unwrap();
+    if let Some(text) = text {
+        font.draw(
+            canvas,
+            (SCREEN_WIDTH - 1 - font.measure(text)) as i32,
+            0,
+            text,
+            Color::RGB(255, 255, 255),
+        );
+    }
+}
+
+pub fn draw_dialog(canvas: &mut Canvas<Window>, font: &mut Font, lines: &[&str]) {
+    let width = lines
+        .iter()
+        .map(|line| font.measure(line))
+        .max()
+        .unwrap_or(0)
+        + 20;
+    let height = lines.len() as u32 * 10 + 10;
+    let x = (SCREEN_WIDTH as i32 - width as i32) / 2;
+    let y = HUD_HEIGHT as i32 + ((SCREEN_HEIGHT - HUD_HEIGHT) as i32 - height as i32) / 2;
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas
+        .fill_rect(Rect::new(x - 1, y - 1, width + 2, height + 2))
+        .unwrap();
+    canvas.set_draw_color(Color::RGB(215, 227, 244));
+    canvas.fill_rect(Rect::new(x, y, width, height)).unwrap();
+    for (i, line) in lines.iter().enumerate() {
+        font.draw(
+            canvas,
+            x + (width - font.measure(line)) as i32 / 2,
+            y + 5 + i as i32 * 10,
+            line,
+            Color::RGB(0, 0, 0),
+        );
+    }
+}
+
+fn food_sprite(food: Food) -> i32 {
+    match food {
+        Food::Normal => 140,
+        Food::Bonus => 350,
+        Food::Golden => 360,
+        Food::Shrink => 370,
+        Food::SpeedUp => 380,
+        Food::SlowDown => 390,
+    }
+}
+
+// Shows each timer from Simulation::timers in the HUD after the score as the
+// food's sprite and a bar of the time left.
+pub fn draw_timers(
+    canvas: &mut Canvas<Window>,
+    font: &mut Font,
+    tiles: &Texture,
+    score: &str,
+    timers: &[(Food, u32, u32)],
+) {
+    let bar_width = 16;
+    let mut x = font.measure(score) as i32 + 6;
+    for &(food, left, total) in timers {
+        canvas
+            .copy(
+                tiles,
+                Some(Rect::new(food_sprite(food), 0, 10, 10)),
+                Some(Rect::new(x, 0, 10, 10)),
+            )
+            .unwrap();
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        canvas
+            .draw_rect(Rect::new(x + 11, 3, bar_width + 2, 4))
+            .unwrap();
+        let filled = bar_width * left / total.max(1);
+        if filled > 0 {
+            canvas.fill_rect(Rect::new(x + 12, 4, filled, 2)).unwrap();
+        }
+        x += 11 + bar_width as i32 + 6;
+    }
+}
+
+// Shows which part of a scrolled list is visible, starting from the row
+// first. Nothing is drawn when all rows fit.
+pub fn draw_scrollbar(
+    canvas: &mut Canvas<Window>,
+    area: Rect,
+    first: usize,
+    visible: usize,
+    rows: usize,
+) {
+    if rows <= visible {
+        return;
+    }
+    canvas.set_draw_color(Color::RGB(170, 180, 200));
+    canvas.fill_rect(area).unwrap();
+    let thumb = (area.height() * visible as u32 / rows as u32).max(4);
+    let offset = (area.height() - thumb) * first as u32 / (rows - visible) as u32;
+    canvas.set_draw_color(Color::RGB(80, 80, 80));
+    canvas
+        .fill_rect(Rect::new(
+            area.x(),
+            area.y() + offset as i32,
+            area.width(),
+            thumb,
+        ))
+        .unwrap();
+}
+
+// A small picture of a map's walls, portals and start position that fits in
+// the area. Large maps get less than a pixel per tile.
+pub fn draw_preview(canvas: &mut Canvas<Window>, map: &Map, area: Rect) {
+    let scale = (area.width() as f32 / map.width as f32)
+        .min(area.height() as f32 / map.height as f32)
+        .min(MAX_TILE_SIZE as f32);
+    let scale = if scale >= 1.0 { scale.floor() } else { scale };
+    let width = ((map.width as f32 * scale).round() as u32).min(area.width());
+    let height = ((map.height as f32 * scale).round() as u32).min(area.height());
+    let x = area.x() + (area.width() - width) as i32 / 2;
+    let y = area.y() + (area.height() - height) as i32 / 2;
+    let tile_rect = |tile_x: usize, tile_y: usize, min_size: i32| {
+        let left = (tile_x as f32 * scale) as i32;
+        let top = (tile_y as f32 * scale) as i32;
+        let right = ((tile_x + 1) as f32 * scale) as i32;
+        let bottom = ((tile_y + 1) as f32 * scale) as i32;
+        Rect::new(
+            x + left,
+            y + top,
+            (right - left).max(min_size) as u32,
+            (bottom - top).max(min_size) as u32,
+        )
+    };
+
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas
+        .draw_rect(Rect::new(x - 1, y - 1, width + 2, height + 2))
+        .unwrap();
+    canvas.set_draw_color(Color::RGB(255, 255, 255));
+    canvas.fill_rect(Rect::new(x, y, width, height)).unwrap();
+
+    let mut walls = Vec::new();
+    let mut portals = Vec::new();
+    for tile_x in 0..map.width {
+        for tile_y in 0..map.height {
+            let tile = map.tile(tile_x, tile_y);
+            if tile.is_wall() {
+                walls.push(tile_rect(tile_x, tile_y, 1));
+            } else if tile.is_portal() {
+                portals.push(tile_rect(tile_x, tile_y, 1));
+            }
+        }
+    }
+    canvas.set_draw_color(Color::RGB(60, 60, 80));
+    canvas.fill_rects(&walls).unwrap();
+    canvas.set_draw_color(Color::RGB(130, 80, 200));
+    canvas.fill_rects(&portals).unwrap();
+
+    canvas.set_draw_color(Color::RGB(220, 40, 40));
+    canvas
+        .fill_rect(tile_rect(map.snake_x, map.snake_y, 2))
+        .unwrap();
+}
+
+// Blanks out the snake, used to make a dead snake blink.
+pub fn hide_snake(canvas: &mut Canvas<Window>, map: &Map) {
+    let layout = Layout::new(map);
+    canvas.set_draw_color(Color::RGB(215, 227, 244));
+    for x in 0..map.width {
+        for y in 0..map.height {
+            if matches!(
+                map.tile(x, y),
+                Tile::SnakeVertical(_)
+                    | Tile::SnakeHorizontal(_)
+                    | Tile::SnakeTurn(_, _, _)
+                    | Tile::SnakeTail(_, _)
+            ) {
+                canvas.fill_rect(layout.tile_rect(x, y)).unwrap();
+            }
+        }
+    }
+}
+
+fn draw_tile(canvas: &mut Canvas<Window>, tiles: &Texture, tile: Tile, target_rect: Rect) {
+    match tile {
+        Tile::Empty => {
+            canvas.set_draw_color(Color::RGB(215, 227, 244));
+            canvas.fill_rect(target_rect).unwrap();
+        }
+        tile => {
+            canvas
+                .copy(
+                    tiles,
+                    Some(Rect::new(
+                        match tile {
+                            Tile::Wall(i) => 150 + 10 * i32::from(i),
+                            Tile::Food(food) => food_sprite(food),
+                            Tile::Portal(id) => 310 + 10 * i32::from(id % 4),
+                            Tile::SnakeVertical(_) => 120,
+                            Tile::SnakeHorizontal(_) => 130,
+                            Tile::SnakeTail(_, Direction::Up) => 60,
+                            Tile::SnakeTail(_, Direction::Right) => 70,
+                            Tile::SnakeTail(_, Direction::Down) => 40,
+                            Tile::SnakeTail(_, Direction::Left) => 50,
+                            Tile::SnakeHead(_, Direction::Up) => 0,
+                            Tile::SnakeHead(_, Direction::Right) => 10,
+                            Tile::SnakeHead(_, Direction::Down) => 20,
+                            Tile::SnakeHead(_, Direction::Left) => 30,
+                            Tile::SnakeTurn(_, Direction::Up, false)
+                            | Tile::SnakeTurn(_, Direction::Left, true) => 110,
+                            Tile::SnakeTurn(_, Direction::Right, false)
+                            | Tile::SnakeTurn(_, Direction::Up, true) => 80,
+                            Tile::SnakeTurn(_, Direction::Down, false)
+                            | Tile::SnakeTurn(_, Direction::Right, true) => 90,
+                            Tile::SnakeTurn(_, Direction::Left, false)
+                            | Tile::SnakeTurn(_, Direction::Down, true) => 100,
+                            _ => unreachable!(),
+                        },
+                        // Each snake has its own row of differently colored
+                        // sprites. There are rows for two snakes.
+                        10 * i32::from(tile.owner().unwrap_or(0)),
+                        10,
+                        10,
+                    )),
+                    Some(target_rect),
+                )
+                .unwrap();
+        }
+    }
+}
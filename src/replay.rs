@@ -0,0 +1,129 @@
+// SPDX-FileCopyrightText: 2019 Tuomas Siipola
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::time::Duration;
+
+use sdl2::event::{Event, EventPollIterator};
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::Window;
+
+use crate::audio::{self, Audio, Sound};
+use crate::font::Font;
+use crate::input::{Bindings, InputAction};
+use crate::map::Map;
+use crate::recording::Recording;
+use crate::render;
+use crate::simulation::{Player, Simulation};
+use crate::state::{Action, State};
+
+const BLINK_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct Replay {
+    simulation: Simulation,
+    recording: Recording,
+    tick: usize,
+    snake_show: bool,
+}
+
+impl Replay {
+    pub fn new(map: &Map, recording: Recording) -> Replay {
+        Replay {
+            simulation: Simulation::new(map, recording.seed, 1),
+            recording,
+            tick: 0,
+            snake_show: true,
+        }
+    }
+
+    fn player(&self) -> &Player {
+        &self.simulation.players[0]
+    }
+
+    fn finished(&self) -> bool {
+        !self.player().alive || self.tick >= self.recording.inputs.len()
+    }
+}
+
+impl State for Replay {
+    fn handle_events(
+        &mut self,
+        events: EventPollIterator,
+        bindings: &mut Bindings,
+        _audio: &mut Audio,
+    ) -> Action {
+        for event in events {
+            match event {
+                Event::Quit { .. } => return Action::Quit,
+                Event::KeyDown {
+                    scancode: Some(scancode),
+                    ..
+                } if bindings.action(scancode) == Some(InputAction::Back) => {
+                    return Action::Pop(None);
+                }
+                _ => {}
+            }
+        }
+        Action::None
+    }
+
+    fn tick(&mut self, audio: &mut Audio) -> Action {
+        if self.finished() {
+            self.snake_show = !self.snake_show;
+        } else {
+            let direction = self.recording.inputs[self.tick];
+            self.tick += 1;
+            if direction != self.player().snake.head.direction {
+                audio.play(Sound::Turn);
+            }
+            let events = self.simulation.step(&[direction]);
+            audio.play_events(&events);
+        }
+        Action::None
+    }
+
+    fn tick_interval(&self) -> Duration {
+        if self.finished() {
+            BLINK_INTERVAL
+        } else {
+            let speed = self.recording.speed;
+            let interval = speed.tick_interval(self.player().score);
+            self.simulation.adjust_interval(interval)
+        }
+    }
+
+    fn music(&self) -> Option<&str> {
+        Some(audio::map_music(&self.simulation.map))
+    }
+
+    fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        font: &mut Font,
+        tiles: &Texture,
+        _logo: &Texture,
+        bindings: &Bindings,
+    ) {
+        let status = if !self.finished() {
+            Some("Replay".to_string())
+        } else if self.snake_show {
+            Some(format!("{} menu", bindings.key_name(InputAction::Back)))
+        } else {
+            None
+        };
+        let score = format!("Score: {}", self.player().score);
+        render::draw_game(
+            canvas,
+            font,
+            tiles,
+            &self.simulation.map,
+            &score,
+            status.as_deref(),
+        );
+        if !self.finished() {
+            render::draw_timers(canvas, font, tiles, &score, &self.simulation.timers());
+        }
+        if !self.snake_show {
+            render::hide_snake(canvas, &self.simulation.map);
+        }
+    }
+}
@@ -0,0 +1,181 @@
+// SPDX-FileCopyrightText: 2019 Tuomas Siipola
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use sdl2::event::{Event, EventPollIterator};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::Window;
+
+use crate::audio::{Audio, Sound};
+use crate::font::Font;
+use crate::input::{Bindings, InputAction};
+use crate::map::Map;
+use crate::recording::{self, Recording};
+use crate::render;
+use crate::replay::Replay;
+use crate::state::{Action, State, Transition};
+
+const LIST_Y: i32 = 50;
+// Rows shown at once, leaving room for the message at the bottom.
+const VISIBLE_ROWS: usize = 17;
+
+pub struct ReplayMenu {
+    maps: Vec<Map>,
+    replays: Vec<Recording>,
+    selected_replay: usize,
+    scroll: usize,
+    message: Option<String>,
+}
+
+impl ReplayMenu {
+    pub fn new(maps: Vec<Map>) -> ReplayMenu {
+        ReplayMenu {
+            maps,
+            replays: recording::list_replays()
+                .into_iter()
+                .filter_map(|path| match Recording::load(&path) {
+                    Ok(recording) => Some(recording),
+                    Err(err) => {
+                        eprintln!("{}: {}", path.display(), err);
+                        None
+                    }
+                })
+                .collect(),
+            selected_replay: 0,
+            scroll: 0,
+            message: None,
+        }
+    }
+
+    // The game may have been played with the borders of the map changed.
+    fn find_map(&self, recording: &Recording) -> Option<Map> {
+        self.maps
+            .iter()
+            .filter(|map| map.name == recording.map_name)
+            .flat_map(|map| {
+                let mut toggled = map.clone();
+                toggled.set_wrap(!map.wrap);
+                vec![map.clone(), toggled]
+            })
+            .find(|map| map.content_hash() == recording.map_hash)
+    }
+
+    fn scroll_to_selected(&mut self) {
+        if self.selected_replay < self.scroll {
+            self.scroll = self.selected_replay;
+        } else if self.selected_replay >= self.scroll + VISIBLE_ROWS {
+            self.scroll = self.selected_replay + 1 - VISIBLE_ROWS;
+        }
+    }
+}
+
+impl State for ReplayMenu {
+    fn handle_events(
+        &mut self,
+        events: EventPollIterator,
+        bindings: &mut Bindings,
+        audio: &mut Audio,
+    ) -> Action {
+        for event in events {
+            match event {
+                Event::Quit { .. } => return Action::Quit,
+                Event::KeyDown {
+                    scancode: Some(scancode),
+                    ..
+                } => match bindings.action(scancode) {
+                    Some(InputAction::Confirm) if !self.replays.is_empty() => {
+                        let recording = &self.replays[self.selected_replay];
+                        match self.find_map(recording) {
+                            Some(map) => {
+                                audio.play(Sound::Start);
+                                return Action::Push(
+                                    Box::new(Replay::new(&map, recording.clone())),
+                                    Transition::Fade,
+                                );
+                            }
+                            None => {
+                                self.message = Some(format!("Map {} not found", recording.map_name))
+                            }
+                        }
+                    }
+                    Some(InputAction::Up) if !self.replays.is_empty() => {
+                        audio.play(Sound::Navigate);
+                        if self.selected_replay == 0 {
+                            self.selected_replay = self.replays.len() - 1;
+                        } else {
+                            self.selected_replay -= 1;
+                        }
+                        self.scroll_to_selected();
+                        self.message = None;
+                    }
+                    Some(InputAction::Down) if !self.replays.is_empty() => {
+                        audio.play(Sound::Navigate);
+                        if self.selected_replay == self.replays.len() - 1 {
+                            self.selected_replay = 0;
+                        } else {
+                            self.selected_replay += 1;
+                        }
+                        self.scroll_to_selected();
+                        self.message = None;
+                    }
+                    Some(InputAction::Back) => return Action::Pop(None),
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        Action::None
+    }
+
+    fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        font: &mut Font,
+        _tiles: &Texture,
+        _logo: &Texture,
+        _bindings: &Bindings,
+    ) {
+        canvas.set_draw_color(Color::RGB(215, 227, 244));
+        canvas.clear();
+
+        font.draw(
+            canvas,
+            ((320 - font.measure("Replays")) / 2) as i32,
+            20,
+            "Replays",
+            Color::RGB(0, 0, 0),
+        );
+
+        if self.replays.is_empty() {
+            font.draw(canvas, 110, LIST_Y, "No replays", Color::RGB(0, 0, 0));
+        }
+
+        let visible = self
+            .replays
+            .iter()
+            .enumerate()
+            .skip(self.scroll)
+            .take(VISIBLE_ROWS);
+        for (row, (i, recording)) in visible.enumerate() {
+            font.draw(
+                canvas,
+                if i == self.selected_replay { 120 } else { 110 },
+                LIST_Y + row as i32 * 10,
+                &format!("{}   Seed: {}", recording.map_name, recording.seed),
+                Color::RGB(0, 0, 0),
+            );
+        }
+        render::draw_scrollbar(
+            canvas,
+            Rect::new(300, LIST_Y, 2, VISIBLE_ROWS as u32 * 10),
+            self.scroll,
+            VISIBLE_ROWS,
+            self.replays.len(),
+        );
+
+        if let Some(message) = &self.message {
+            font.draw(canvas, 1, 230, message, Color::RGB(0, 0, 0));
+        }
+    }
+}
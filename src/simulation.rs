@@ -0,0 +1,618 @@
+// SPDX-FileCopyrightText: 2019 Tuomas Siipola
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::time::Duration;
+
+use rand::{thread_rng, Rng, SeedableRng};
+use rand_pcg::Pcg32;
+
+use crate::direction::Direction;
+use crate::food::{Food, EFFECT_TICKS};
+use crate::map::{self, Map};
+use crate::snake::Snake;
+use crate::tile::Tile;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum GameEvent {
+    FoodEaten(usize, Food),
+    Died(usize),
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum DeathCause {
+    Wall,
+    OwnBody,
+    OtherSnake,
+    HeadOn,
+}
+
+pub struct Player {
+    pub snake: Snake,
+    pub score: u32,
+    pub alive: bool,
+    pub death: Option<DeathCause>,
+}
+
+// Shrink food takes this many tiles off the tail but leaves at least
+// MIN_LENGTH tiles of the snake.
+const SHRINK_LENGTH: usize = 5;
+const MIN_LENGTH: usize = map::MIN_LENGTH as usize;
+
+pub struct Simulation {
+    pub map: Map,
+    pub players: Vec<Player>,
+    pub seed: u32,
+    rng: Pcg32,
+    ticks: u32,
+    // Food that disappears on the given tick unless it is eaten first.
+    expiring: Vec<(i32, i32, Food, u32)>,
+    // The current speed change and how many ticks it has left.
+    effect: Option<(Food, u32)>,
+}
+
+pub fn random_seed() -> u32 {
+    thread_rng().gen()
+}
+
+// Maps only mark one start position, so the second snake starts from the
+// closest free tile to the opposite side of the map, heading the other way.
+// Maps aren't always symmetric, so if that way is blocked the snake heads
+// whichever way is free instead.
+fn second_start(map: &Map) -> (usize, usize, Direction) {
+    let first = (map.snake_x, map.snake_y);
+    let mirrored = (map.width - 1 - map.snake_x, map.height - 1 - map.snake_y);
+    let distance = |(x, y, _): (usize, usize, Direction)| {
+        (x as i32 - mirrored.0 as i32).abs() + (y as i32 - mirrored.1 as i32).abs()
+    };
+    let preferred = map.start_direction.opposite();
+    let directions = [
+        preferred,
+        Direction::Up,
+        Direction::Right,
+        Direction::Down,
+        Direction::Left,
+    ];
+    let free = |(x, y): (usize, usize)| map.tile(x, y).is_empty() && (x, y) != first;
+    (0..map.width)
+        .flat_map(|x| (0..map.height).map(move |y| (x, y)))
+        .filter(|&position| free(position))
+        .filter_map(|(x, y)| {
+            let direction = directions
+                .iter()
+                .find(|direction| map.step(x, y, **direction).is_some_and(free))?;
+            Some((x, y, *direction))
+        })
+        .min_by_key(|&start| distance(start))
+        .unwrap_or((mirrored.0, mirrored.1, preferred))
+}
+
+impl Simulation {
+    pub fn new(map: &Map, seed: u32, player_count: usize) -> Simulation {
+        let mut players = vec![Player {
+            snake: Snake::new(
+                map.snake_x as i32,
+                map.snake_y as i32,
+                map.start_direction,
+                map.start_length,
+            ),
+            score: 0,
+            alive: true,
+            death: None,
+        }];
+        if player_count > 1 {
+            let (x, y, direction) = second_start(map);
+            players.push(Player {
+                snake: Snake::new(x as i32, y as i32, direction, map.start_length),
+                score: 0,
+                alive: true,
+                death: None,
+            });
+        }
+        let mut simulation = Simulation {
+            players,
+            map: map.clone(),
+            seed,
+            rng: Pcg32::seed_from_u64(u64::from(seed)),
+            ticks: 0,
+            expiring: Vec::new(),
+            effect: None,
+        };
+        // The snakes are put on the map first so that no food ends up under
+        // them.
+        for i in 0..simulation.players.len() {
+            let head = &simulation.players[i].snake.head;
+            let (x, y, direction) = (head.x, head.y, head.direction);
+            simulation.set_tile(x, y, Tile::SnakeHead(i as u8, direction));
+        }
+        simulation.place_food();
+        simulation
+    }
+
+    // Moves every living snake one tile. All snakes move at once: collisions
+    // are checked only after every snake has moved, and snakes whose heads
+    // meet both die.
+    pub fn step(&mut self, directions: &[Direction]) -> Vec<GameEvent> {
+        let mut events = Vec::new();
+        self.ticks += 1;
+        self.effect = match self.effect {
+            Some((food, ticks)) if ticks > 1 => Some((food, ticks - 1)),
+            _ => None,
+        };
+
+        for i in 0..self.players.len() {
+            if self.players[i].alive {
+                self.move_tail(i);
+            }
+        }
+
+        let mut off_map = Vec::new();
+        for (i, &direction) in directions.iter().enumerate() {
+            if self.players[i].alive && !self.move_head(i, direction) {
+                off_map.push(i);
+            }
+        }
+
+        let mut eaten = Vec::new();
+        let mut died = Vec::new();
+        for (i, player) in self.players.iter().enumerate() {
+            if !player.alive {
+                continue;
+            }
+            let head = &player.snake.head;
+            if off_map.contains(&i) {
+                died.push((i, DeathCause::Wall));
+                continue;
+            }
+            let head_on = self.players.iter().enumerate().any(|(j, other)| {
+                j != i
+                    && other.alive
+                    && !off_map.contains(&j)
+                    && other.snake.head.x == head.x
+                    && other.snake.head.y == head.y
+            });
+            let tile = self.tile(head.x, head.y);
+            match tile {
+                _ if head_on => died.push((i, DeathCause::HeadOn)),
+                Tile::Food(food) => eaten.push((i, food)),
+                Tile::Wall(_) => died.push((i, DeathCause::Wall)),
+                Tile::SnakeVertical(_)
+                | Tile::SnakeHorizontal(_)
+                | Tile::SnakeTurn(_, _, _)
+                | Tile::SnakeHead(_, _)
+                | Tile::SnakeTail(_, _) => died.push((
+                    i,
+                    if tile.owner() == Some(i as u8) {
+                        DeathCause::OwnBody
+                    } else {
+                        DeathCause::OtherSnake
+                    },
+                )),
+                _ => {}
+            }
+        }
+
+        for (i, cause) in died {
+            self.players[i].alive = false;
+            self.players[i].death = Some(cause);
+            events.push(GameEvent::Died(i));
+        }
+
+        for i in 0..self.players.len() {
+            let head = &self.players[i].snake.head;
+            if self.players[i].alive {
+                let (x, y, direction) = (head.x, head.y, head.direction);
+                self.set_tile(x, y, Tile::SnakeHead(i as u8, direction));
+            }
+        }
+
+        for (i, food) in eaten {
+            let head = &self.players[i].snake.head;
+            let (x, y) = (head.x, head.y);
+            self.expiring
+                .retain(|&(food_x, food_y, _, _)| (food_x, food_y) != (x, y));
+            self.place_food();
+            self.eat(i, food, &mut events);
+        }
+
+        self.expire_food();
+
+        events
+    }
+
+    // Timed things to show to the player: food about to disappear and speed
+    // changes, with the ticks they have left out of their full length.
+    pub fn timers(&self) -> Vec<(Food, u32, u32)> {
+        let mut timers: Vec<_> = self
+            .expiring
+            .iter()
+            .map(|&(_, _, food, tick)| (food, tick - self.ticks, food.lifetime().unwrap()))
+            .collect();
+        if let Some((food, ticks)) = self.effect {
+            timers.push((food, ticks, EFFECT_TICKS));
+        }
+        timers
+    }
+
+    // Speed changes make the whole game run faster or slower.
+    pub fn adjust_interval(&self, interval: Duration) -> Duration {
+        match self.effect {
+            Some((Food::SpeedUp, _)) => interval * 2 / 3,
+            Some((Food::SlowDown, _)) => interval * 3 / 2,
+            _ => interval,
+        }
+    }
+
+    fn eat(&mut self, i: usize, food: Food, events: &mut Vec<GameEvent>) {
+        let player = &mut self.players[i];
+        player.snake.grow = player.snake.grow.saturating_add(food.growth());
+        player.score += food.points();
+        match food {
+            Food::Shrink => self.shrink(i),
+            Food::SpeedUp | Food::SlowDown => self.effect = Some((food, EFFECT_TICKS)),
+            _ => {}
+        }
+        events.push(GameEvent::FoodEaten(i, food));
+    }
+
+    // A snake still growing from its start keeps the growth it needs to
+    // reach MIN_LENGTH, otherwise its tail would catch up with its head.
+    fn shrink(&mut self, i: usize) {
+        let owner = Some(i as u8);
+        let length = |map: &Map| map.tiles().filter(|tile| tile.owner() == owner).count();
+        let needed = MIN_LENGTH.saturating_sub(length(&self.map));
+        let snake = &mut self.players[i].snake;
+        snake.grow = snake.grow.min(needed as u8);
+        for _ in 0..SHRINK_LENGTH {
+            if length(&self.map) <= MIN_LENGTH {
+                break;
+            }
+            self.move_tail(i);
+        }
+    }
+
+    // Removes food whose time is up and puts new food somewhere else.
+    fn expire_food(&mut self) {
+        let ticks = self.ticks;
+        let (expired, remaining) = self
+            .expiring
+            .iter()
+            .partition(|&&(_, _, _, tick)| tick <= ticks);
+        self.expiring = remaining;
+        for (x, y, _, _) in expired {
+            self.set_tile(x, y, Tile::Empty);
+            self.place_food();
+        }
+    }
+
+    fn move_tail(&mut self, i: usize) {
+        let snake = &mut self.players[i].snake;
+        if snake.grow > 0 {
+            snake.grow -= 1;
+            return;
+        }
+
+        let (x, y) = (snake.tail.x, snake.tail.y);
+        // The tail normally picks up turns as it moves onto them, but the
+        // start tile is never moved onto, so a turn made on the very first
+        // tick has to be picked up here.
+        if let Tile::SnakeTurn(_, direction, _) = self.tile(x, y) {
+            self.players[i].snake.tail.direction = direction;
+        }
+        self.set_tile(x, y, Tile::Empty);
+
+        let tail = &mut self.players[i].snake.tail;
+        tail.update(&self.map);
+        match self.map.tile(tail.x as usize, tail.y as usize) {
+            Tile::SnakeTurn(_, direction, _) => tail.direction = direction,
+            Tile::SnakeVertical(_) | Tile::SnakeHorizontal(_) => {}
+            _ => unreachable!(),
+        };
+
+        let (x, y, direction) = (tail.x, tail.y, tail.direction);
+        self.set_tile(x, y, Tile::SnakeTail(i as u8, direction));
+    }
+
+    // Returns false if the snake ran over the edge of a map that doesn't wrap,
+    // in which case the head stays where it was.
+    fn move_head(&mut self, i: usize, direction: Direction) -> bool {
+        let owner = i as u8;
+        let head = &mut self.players[i].snake.head;
+        let (x, y) = (head.x, head.y);
+
+        let tile = if head.direction != direction && direction.opposite() != head.direction {
+            let clockwise = matches!(
+                (head.direction, direction),
+                (Direction::Right, Direction::Down)
+                    | (Direction::Down, Direction::Left)
+                    | (Direction::Left, Direction::Up)
+                    | (Direction::Up, Direction::Right)
+            );
+            head.direction = direction;
+            Tile::SnakeTurn(owner, direction, clockwise)
+        } else {
+            match head.direction {
+                Direction::Up | Direction::Down => Tile::SnakeVertical(owner),
+                Direction::Right | Direction::Left => Tile::SnakeHorizontal(owner),
+            }
+        };
+        if !head.update(&self.map) {
+            return false;
+        }
+
+        self.set_tile(x, y, tile);
+        true
+    }
+
+    fn tile(&self, x: i32, y: i32) -> Tile {
+        self.map.tile(x as usize, y as usize)
+    }
+
+    fn set_tile(&mut self, x: i32, y: i32, tile: Tile) {
+        self.map.set_tile(x as usize, y as usize, tile);
+    }
+
+    fn has_free_space(&self) -> bool {
+        self.map.tiles().any(|tile| tile.is_empty())
+    }
+
+    fn place_food(&mut self) {
+        if !self.has_free_space() {
+            return;
+        }
+        loop {
+            let (x, y) = (
+                self.rng.gen_range(0, self.map.width as i32),
+                self.rng.gen_range(0, self.map.height as i32),
+            );
+            if self.tile(x, y) != Tile::Empty {
+                continue;
+            }
+            let food = self.choose_food();
+            self.set_tile(x, y, Tile::Food(food));
+            if let Some(lifetime) = food.lifetime() {
+                self.expiring.push((x, y, food, self.ticks + lifetime));
+            }
+            return;
+        }
+    }
+
+    // Maps with only one kind of food don't use the random number generator
+    // here so that they play the same as before there were different kinds.
+    fn choose_food(&mut self) -> Food {
+        let weights = &self.map.food_weights;
+        if weights.len() == 1 {
+            return weights[0].0;
+        }
+        let total: u32 = weights.iter().map(|(_, weight)| weight).sum();
+        let mut roll = self.rng.gen_range(0, total);
+        for &(food, weight) in weights {
+            if roll < weight {
+                return food;
+            }
+            roll -= weight;
+        }
+        unreachable!()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_map(header: &str, rows: &[&str]) -> Map {
+        let text = format!(
+            "natrix-map 1\nname = Test\nwidth = {}\nheight = {}\n{}\n\n{}\n",
+            rows[0].len(),
+            rows.len(),
+            header,
+            rows.join("\n")
+        );
+        Map::parse(text.as_bytes()).unwrap()
+    }
+
+    // Food lands in random places, which would make the snakes grow.
+    fn clear_food(simulation: &mut Simulation) {
+        for x in 0..simulation.map.width {
+            for y in 0..simulation.map.height {
+                if simulation.map.tile(x, y).is_food() {
+                    simulation.map.set_tile(x, y, Tile::Empty);
+                }
+            }
+        }
+        simulation.expiring.clear();
+    }
+
+    fn length(simulation: &Simulation, i: usize) -> usize {
+        let owner = Some(i as u8);
+        simulation
+            .map
+            .tiles()
+            .filter(|tile| tile.owner() == owner)
+            .count()
+    }
+
+    fn food_positions(simulation: &Simulation) -> Vec<(usize, usize)> {
+        let map = &simulation.map;
+        (0..map.width)
+            .flat_map(|x| (0..map.height).map(move |y| (x, y)))
+            .filter(|&(x, y)| map.tile(x, y).is_food())
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_plays_the_same() {
+        let mut rows = ["        "; 8];
+        rows[2] = "  @     ";
+        let map = parse_map("length = 3", &rows);
+        let turns = [
+            Direction::Right,
+            Direction::Down,
+            Direction::Left,
+            Direction::Up,
+        ];
+        let run = |seed| {
+            let mut simulation = Simulation::new(&map, seed, 1);
+            let mut events = Vec::new();
+            for tick in 0..200 {
+                events.extend(simulation.step(&[turns[tick / 3 % 4]]));
+            }
+            (
+                events,
+                simulation.players[0].score,
+                food_positions(&simulation),
+            )
+        };
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7).2, run(8).2);
+    }
+
+    #[test]
+    fn snake_grows_to_start_length() {
+        let map = parse_map("length = 3", &["          ", "  @       ", "          "]);
+        let mut simulation = Simulation::new(&map, 1, 1);
+        for _ in 0..4 {
+            clear_food(&mut simulation);
+            let events = simulation.step(&[Direction::Right]);
+            assert!(!events.contains(&GameEvent::Died(0)));
+        }
+        let snake = &simulation.players[0].snake;
+        assert_eq!((snake.head.x, snake.head.y), (6, 1));
+        assert_eq!((snake.tail.x, snake.tail.y), (4, 1));
+        assert_eq!(length(&simulation, 0), 3);
+    }
+
+    #[test]
+    fn running_into_wall_kills() {
+        let map = parse_map("length = 3\nwrap = off", &["XXXXXX", "X@   X", "XXXXXX"]);
+        let mut simulation = Simulation::new(&map, 1, 1);
+        for _ in 0..3 {
+            clear_food(&mut simulation);
+            simulation.step(&[Direction::Right]);
+        }
+        assert!(simulation.players[0].alive);
+        let events = simulation.step(&[Direction::Right]);
+        assert!(events.contains(&GameEvent::Died(0)));
+        assert_eq!(simulation.players[0].death, Some(DeathCause::Wall));
+    }
+
+    #[test]
+    fn running_off_solid_edge_kills() {
+        let map = parse_map("length = 3\nwrap = off", &["   ", " @ ", "   "]);
+        let mut simulation = Simulation::new(&map, 1, 1);
+        clear_food(&mut simulation);
+        simulation.step(&[Direction::Right]);
+        clear_food(&mut simulation);
+        simulation.step(&[Direction::Right]);
+        assert_eq!(simulation.players[0].death, Some(DeathCause::Wall));
+        let snake = &simulation.players[0].snake;
+        assert_eq!((snake.head.x, snake.head.y), (2, 1));
+    }
+
+    #[test]
+    fn running_into_own_body_kills() {
+        let map = parse_map(
+            "length = 5",
+            &["      ", "      ", "  @   ", "      ", "      "],
+        );
+        let mut simulation = Simulation::new(&map, 1, 1);
+        let turns = [Direction::Right, Direction::Down, Direction::Left];
+        for direction in turns.iter() {
+            clear_food(&mut simulation);
+            simulation.step(&[*direction]);
+        }
+        assert!(simulation.players[0].alive);
+        clear_food(&mut simulation);
+        simulation.step(&[Direction::Up]);
+        assert_eq!(simulation.players[0].death, Some(DeathCause::OwnBody));
+    }
+
+    #[test]
+    fn heads_meeting_both_die() {
+        let map = parse_map("length = 3", &["XXXXXXX", "X@    X", "XXXXXXX"]);
+        let mut simulation = Simulation::new(&map, 1, 2);
+        let snake = &simulation.players[1].snake;
+        assert_eq!((snake.head.x, snake.head.y), (5, 1));
+        for _ in 0..2 {
+            clear_food(&mut simulation);
+            simulation.step(&[Direction::Right, Direction::Left]);
+        }
+        assert_eq!(simulation.players[0].death, Some(DeathCause::HeadOn));
+        assert_eq!(simulation.players[1].death, Some(DeathCause::HeadOn));
+    }
+
+    #[test]
+    fn second_snake_avoids_blocked_start() {
+        let rows = ["XXXXXX", "X@   X", "XXXX X", "XXXX X"];
+        let map = parse_map("length = 3", &rows);
+        let mut simulation = Simulation::new(&map, 1, 2);
+        let snake = &simulation.players[1].snake;
+        assert_eq!((snake.head.x, snake.head.y), (4, 2));
+        assert!(snake.head.direction == Direction::Up);
+        clear_food(&mut simulation);
+        simulation.step(&[Direction::Right, Direction::Up]);
+        assert!(simulation.players.iter().all(|player| player.alive));
+    }
+
+    #[test]
+    fn no_food_under_start() {
+        let map = parse_map("length = 3", &["X@ X"]);
+        for seed in 0..20 {
+            let simulation = Simulation::new(&map, seed, 1);
+            assert!(matches!(simulation.map.tile(1, 0), Tile::SnakeHead(0, _)));
+            assert!(simulation.map.tile(2, 0).is_food());
+        }
+    }
+
+    // Puts shrink food in front of the snake and moves onto it.
+    fn eat_shrink(simulation: &mut Simulation) {
+        clear_food(simulation);
+        let head = &simulation.players[0].snake.head;
+        let (x, y) = (head.x as usize + 1, head.y as usize);
+        simulation.map.set_tile(x, y, Tile::Food(Food::Shrink));
+        let events = simulation.step(&[Direction::Right]);
+        assert!(events.contains(&GameEvent::FoodEaten(0, Food::Shrink)));
+    }
+
+    #[test]
+    fn shrink_while_growing_from_start() {
+        let map = parse_map("length = 3", &["            ", "  @         "]);
+        let mut simulation = Simulation::new(&map, 115, 1);
+        eat_shrink(&mut simulation);
+        assert_eq!(length(&simulation, 0), 2);
+        for _ in 0..5 {
+            clear_food(&mut simulation);
+            simulation.step(&[Direction::Right]);
+        }
+        assert!(simulation.players[0].alive);
+        assert_eq!(length(&simulation, 0), 3);
+    }
+
+    #[test]
+    fn shrink_keeps_min_length() {
+        let map = parse_map("length = 5", &["            ", "  @         "]);
+        let mut simulation = Simulation::new(&map, 1, 1);
+        for _ in 0..4 {
+            clear_food(&mut simulation);
+            simulation.step(&[Direction::Right]);
+        }
+        assert_eq!(length(&simulation, 0), 5);
+        eat_shrink(&mut simulation);
+        assert_eq!(length(&simulation, 0), 3);
+        assert_eq!(simulation.players[0].snake.grow, 0);
+    }
+
+    #[test]
+    fn running_into_other_snake_kills() {
+        let map = parse_map("length = 3", &["XXXXXXXX", "X@     X", "XXXXXXXX"]);
+        let mut simulation = Simulation::new(&map, 1, 2);
+        clear_food(&mut simulation);
+        simulation.step(&[Direction::Right, Direction::Left]);
+        clear_food(&mut simulation);
+        simulation.step(&[Direction::Right, Direction::Left]);
+        assert!(simulation.players.iter().all(|player| player.alive));
+        clear_food(&mut simulation);
+        simulation.step(&[Direction::Right, Direction::Left]);
+        assert_eq!(simulation.players[0].death, Some(DeathCause::OtherSnake));
+        assert_eq!(simulation.players[1].death, Some(DeathCause::OtherSnake));
+    }
+}
@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use crate::direction::Direction;
+use crate::map::Map;
 
 pub struct SnakeEnd {
     pub x: i32,
@@ -10,36 +11,16 @@ pub struct SnakeEnd {
 }
 
 impl SnakeEnd {
-    pub fn update(&mut self) {
-        match self.direction {
-            Direction::Up => {
-                if self.y == 0 {
-                    self.y = 23 - 1;
-                } else {
-                    self.y -= 1;
-                }
-            }
-            Direction::Right => {
-                if self.x == 32 - 1 {
-                    self.x = 0;
-                } else {
-                    self.x += 1;
-                }
-            }
-            Direction::Down => {
-                if self.y == 23 - 1 {
-                    self.y = 0;
-                } else {
-                    self.y += 1;
-                }
-            }
-            Direction::Left => {
-                if self.x == 0 {
-                    self.x = 32 - 1;
-                } else {
-                    self.x -= 1;
-                }
+    // Moves one tile forward, through portals if there are any. Returns false
+    // without moving if that would leave a map that doesn't wrap.
+    pub fn update(&mut self, map: &Map) -> bool {
+        match map.step(self.x as usize, self.y as usize, self.direction) {
+            Some((x, y)) => {
+                self.x = x as i32;
+                self.y = y as i32;
+                true
             }
+            None => false,
         }
     }
 }
@@ -51,11 +32,11 @@ pub struct Snake {
 }
 
 impl Snake {
-    pub fn new(x: i32, y: i32, direction: Direction) -> Snake {
+    pub fn new(x: i32, y: i32, direction: Direction, length: u8) -> Snake {
         Snake {
             head: SnakeEnd { x, y, direction },
             tail: SnakeEnd { x, y, direction },
-            grow: 10,
+            grow: length - 1,
         }
     }
 }
@@ -0,0 +1,43 @@
+// SPDX-FileCopyrightText: 2019 Tuomas Siipola
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::time::Duration;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Speed {
+    Slow,
+    Normal,
+    Fast,
+    Increasing,
+}
+
+const SPEEDS: [Speed; 4] = [Speed::Slow, Speed::Normal, Speed::Fast, Speed::Increasing];
+
+impl Speed {
+    pub fn name(self) -> &'static str {
+        match self {
+            Speed::Slow => "Slow",
+            Speed::Normal => "Normal",
+            Speed::Fast => "Fast",
+            Speed::Increasing => "Increasing",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Speed> {
+        SPEEDS
+            .iter()
+            .cloned()
+            .find(|speed| speed.name().eq_ignore_ascii_case(name))
+    }
+
+    // Time between snake moves. Increasing starts slow and gets faster with
+    // every food eaten until it is faster than Fast.
+    pub fn tick_interval(self, score: u32) -> Duration {
+        Duration::from_millis(match self {
+            Speed::Slow => 150,
+            Speed::Normal => 100,
+            Speed::Fast => 60,
+            Speed::Increasing => 150 - u64::from(score.min(20)) * 5,
+        })
+    }
+}
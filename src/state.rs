@@ -1,25 +1,213 @@
 // SPDX-FileCopyrightText: 2019 Tuomas Siipola
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+use std::thread;
+use std::time::Duration;
+
 use sdl2::event::EventPollIterator;
-use sdl2::render::{Canvas, Texture};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, Texture};
 use sdl2::video::Window;
 
+use crate::audio::Audio;
 use crate::font::Font;
+use crate::input::Bindings;
+use crate::render;
+
+const FADE_STEPS: u32 = 6;
+const DEFAULT_TICK_INTERVAL: Duration = Duration::from_millis(100);
+// Time not yet simulated is capped so that a long stall, e.g. while the window
+// is being dragged, doesn't cause a burst of ticks afterwards.
+const MAX_LAG: Duration = Duration::from_millis(250);
+
+// Passed to the state below when an overlay pops itself off the stack.
+pub enum Signal {
+    Restart,
+    ExitToMenu,
+    // A campaign level was completed and the next one should be started.
+    NextLevel,
+    // A map was saved, so the list of maps needs to be read again.
+    MapsChanged,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Transition {
+    None,
+    Fade,
+}
 
 pub enum Action {
     None,
     Quit,
-    Change(Box<dyn State>),
+    Push(Box<dyn State>, Transition),
+    Replace(Box<dyn State>, Transition),
+    Pop(Option<Signal>),
 }
 
 pub trait State {
-    fn update(
+    fn handle_events(
         &mut self,
         events: EventPollIterator,
+        bindings: &mut Bindings,
+        audio: &mut Audio,
+    ) -> Action;
+
+    // Advances the state by one step. Called every `tick_interval` regardless
+    // of how often frames are drawn.
+    fn tick(&mut self, _audio: &mut Audio) -> Action {
+        Action::None
+    }
+
+    fn tick_interval(&self) -> Duration {
+        DEFAULT_TICK_INTERVAL
+    }
+
+    // Draws the whole screen. Called once per frame.
+    fn draw(
+        &self,
         canvas: &mut Canvas<Window>,
         font: &mut Font,
         tiles: &Texture,
         logo: &Texture,
-    ) -> Action;
+        bindings: &Bindings,
+    );
+
+    // Track in the music directory to play while the state is on top of the stack.
+    fn music(&self) -> Option<&str> {
+        None
+    }
+
+    // Called when the state becomes the top of the stack again.
+    fn resume(&mut self, _signal: Option<Signal>) -> Action {
+        Action::None
+    }
+}
+
+pub struct StateManager {
+    states: Vec<Box<dyn State>>,
+    lag: Duration,
+}
+
+// Darkens whatever is currently on screen. States redraw fully after a
+// transition so no fade in is needed.
+fn fade_out(canvas: &mut Canvas<Window>) {
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, 255 / FADE_STEPS as u8));
+    for _ in 0..FADE_STEPS {
+        canvas
+            .fill_rect(Rect::new(0, 0, render::SCREEN_WIDTH, render::SCREEN_HEIGHT))
+            .unwrap();
+        canvas.present();
+        thread::sleep(Duration::from_millis(30));
+    }
+    canvas.set_blend_mode(BlendMode::None);
+}
+
+impl StateManager {
+    pub fn new(initial_state: Box<dyn State>) -> StateManager {
+        StateManager {
+            states: vec![initial_state],
+            lag: Duration::from_secs(0),
+        }
+    }
+
+    // Returns false once the last state has been popped or a state asked to
+    // quit.
+    fn apply(&mut self, mut action: Action, canvas: &mut Canvas<Window>) -> bool {
+        loop {
+            action = match action {
+                Action::None => return true,
+                Action::Quit => return false,
+                Action::Push(state, transition) => {
+                    if transition == Transition::Fade {
+                        fade_out(canvas);
+                    }
+                    self.states.push(state);
+                    self.lag = Duration::from_secs(0);
+                    return true;
+                }
+                Action::Replace(state, transition) => {
+                    if transition == Transition::Fade {
+                        fade_out(canvas);
+                    }
+                    self.states.pop();
+                    self.states.push(state);
+                    self.lag = Duration::from_secs(0);
+                    return true;
+                }
+                Action::Pop(signal) => {
+                    self.states.pop();
+                    self.lag = Duration::from_secs(0);
+                    match self.states.last_mut() {
+                        Some(state) => state.resume(signal),
+                        None => return false,
+                    }
+                }
+            }
+        }
+    }
+
+    // Handles input and then runs as many fixed ticks as fit in the elapsed
+    // time. Leftover time carries over to the next call.
+    pub fn update(
+        &mut self,
+        events: EventPollIterator,
+        elapsed: Duration,
+        canvas: &mut Canvas<Window>,
+        bindings: &mut Bindings,
+        audio: &mut Audio,
+    ) -> bool {
+        let running = self.run(events, elapsed, canvas, bindings, audio);
+        audio.play_music(self.states.last().and_then(|state| state.music()));
+        running
+    }
+
+    fn run(
+        &mut self,
+        events: EventPollIterator,
+        elapsed: Duration,
+        canvas: &mut Canvas<Window>,
+        bindings: &mut Bindings,
+        audio: &mut Audio,
+    ) -> bool {
+        self.lag = (self.lag + elapsed).min(MAX_LAG);
+
+        let action = match self.states.last_mut() {
+            Some(state) => state.handle_events(events, bindings, audio),
+            None => return false,
+        };
+        if !self.apply(action, canvas) {
+            return false;
+        }
+
+        loop {
+            let state = match self.states.last_mut() {
+                Some(state) => state,
+                None => return false,
+            };
+            let interval = state.tick_interval();
+            if self.lag < interval {
+                return true;
+            }
+            self.lag -= interval;
+            let action = state.tick(audio);
+            if !self.apply(action, canvas) {
+                return false;
+            }
+        }
+    }
+
+    pub fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        font: &mut Font,
+        tiles: &Texture,
+        logo: &Texture,
+        bindings: &Bindings,
+    ) {
+        if let Some(state) = self.states.last() {
+            state.draw(canvas, font, tiles, logo, bindings);
+        }
+    }
 }
@@ -2,31 +2,48 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use crate::direction::Direction;
+use crate::food::Food;
 
 #[derive(Copy, Clone, PartialEq)]
 pub enum Tile {
     Empty,
     Wall(u8),
-    Food,
-    SnakeVertical,
-    SnakeHorizontal,
-    SnakeTurn(Direction, bool),
-    SnakeHead(Direction),
-    SnakeTail(Direction),
+    Food(Food),
+    // Snake tiles carry the index of the snake they belong to.
+    SnakeVertical(u8),
+    SnakeHorizontal(u8),
+    SnakeTurn(u8, Direction, bool),
+    SnakeHead(u8, Direction),
+    SnakeTail(u8, Direction),
+    // Portals come in pairs that share the same number.
+    Portal(u8),
 }
 
 impl Tile {
     pub fn is_empty(self) -> bool {
-        match self {
-            Tile::Empty => true,
-            _ => false,
-        }
+        matches!(self, Tile::Empty)
     }
 
     pub fn is_wall(self) -> bool {
+        matches!(self, Tile::Wall(_))
+    }
+
+    pub fn is_food(self) -> bool {
+        matches!(self, Tile::Food(_))
+    }
+
+    pub fn is_portal(self) -> bool {
+        matches!(self, Tile::Portal(_))
+    }
+
+    pub fn owner(self) -> Option<u8> {
         match self {
-            Tile::Wall(_) => true,
-            _ => false,
+            Tile::SnakeVertical(owner)
+            | Tile::SnakeHorizontal(owner)
+            | Tile::SnakeTurn(owner, _, _)
+            | Tile::SnakeHead(owner, _)
+            | Tile::SnakeTail(owner, _) => Some(owner),
+            _ => None,
         }
     }
 }
@@ -0,0 +1,316 @@
+// SPDX-FileCopyrightText: 2019 Tuomas Siipola
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::time::Duration;
+
+use sdl2::event::{Event, EventPollIterator, WindowEvent};
+use sdl2::keyboard::Scancode;
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::Window;
+
+use crate::ai::{Difficulty, SnakeController};
+use crate::audio::{self, Audio, Sound};
+use crate::direction::Direction;
+use crate::font::Font;
+use crate::input::{Bindings, InputAction, TurnQueue};
+use crate::map::Map;
+use crate::pause::Pause;
+use crate::render;
+use crate::simulation::{random_seed, Simulation};
+use crate::speed::Speed;
+use crate::state::{Action, Signal, State, Transition};
+
+const PLAYERS: usize = 2;
+const ROUNDS_TO_WIN: u32 = 3;
+
+// Two snakes on one map. A round ends when at most one snake is left and the
+// first player to win ROUNDS_TO_WIN rounds wins the match. The second snake is
+// either another player or the computer. There is only ever one computer
+// opponent: maps mark a single start, and tiles.bmp only has sprites for two
+// snakes.
+pub struct Versus {
+    map: Map,
+    speed: Speed,
+    opponent: Option<Difficulty>,
+    simulation: Simulation,
+    turns: Vec<TurnQueue>,
+    controllers: Vec<Option<Box<dyn SnakeController>>>,
+    wins: [u32; PLAYERS],
+    round_over: bool,
+    round_winner: Option<usize>,
+}
+
+impl Versus {
+    pub fn new(map: &Map, speed: Speed, opponent: Option<Difficulty>) -> Versus {
+        Versus {
+            map: map.clone(),
+            speed,
+            opponent,
+            simulation: Simulation::new(map, random_seed(), PLAYERS),
+            turns: (0..PLAYERS).map(|_| TurnQueue::new()).collect(),
+            controllers: vec![None, opponent.map(Difficulty::controller)],
+            wins: [0; PLAYERS],
+            round_over: false,
+            round_winner: None,
+        }
+    }
+
+    fn next_round(&mut self) {
+        self.simulation = Simulation::new(&self.map, random_seed(), PLAYERS);
+        self.turns = (0..PLAYERS).map(|_| TurnQueue::new()).collect();
+        self.controllers = vec![None, self.opponent.map(Difficulty::controller)];
+        self.round_over = false;
+        self.round_winner = None;
+    }
+
+    fn match_winner(&self) -> Option<usize> {
+        self.wins.iter().position(|wins| *wins >= ROUNDS_TO_WIN)
+    }
+
+    fn rematch(&self) -> Action {
+        Action::Replace(
+            Box::new(Versus::new(&self.map, self.speed, self.opponent)),
+            Transition::Fade,
+        )
+    }
+
+    fn player_name(&self, i: usize) -> String {
+        if self.controllers[i].is_some() {
+            "Computer".to_string()
+        } else {
+            format!("Player {}", i + 1)
+        }
+    }
+
+    fn score(&self) -> String {
+        let players = &self.simulation.players;
+        let opponent = if self.controllers[1].is_some() {
+            "CPU"
+        } else {
+            "P2"
+        };
+        format!(
+            "P1: {}   {}: {}",
+            players[0].score, opponent, players[1].score
+        )
+    }
+
+    // Against the computer the first player may use either set of keys.
+    fn player_direction(
+        &self,
+        bindings: &Bindings,
+        scancode: Scancode,
+    ) -> Option<(usize, Direction)> {
+        if self.controllers[1].is_some() {
+            bindings
+                .action(scancode)
+                .and_then(InputAction::direction)
+                .or_else(|| {
+                    bindings
+                        .player_direction(scancode)
+                        .map(|(_, direction)| direction)
+                })
+                .map(|direction| (0, direction))
+        } else {
+            bindings.player_direction(scancode)
+        }
+    }
+
+    fn status(&self) -> String {
+        format!("Rounds: {} - {}", self.wins[0], self.wins[1])
+    }
+
+    fn pause(&self) -> Action {
+        Action::Push(
+            Box::new(Pause::new(
+                self.simulation.map.clone(),
+                self.score(),
+                self.status(),
+            )),
+            Transition::None,
+        )
+    }
+}
+
+impl State for Versus {
+    fn handle_events(
+        &mut self,
+        events: EventPollIterator,
+        bindings: &mut Bindings,
+        audio: &mut Audio,
+    ) -> Action {
+        for event in events {
+            match event {
+                Event::Quit { .. } => return Action::Quit,
+                Event::Window {
+                    win_event: WindowEvent::FocusLost,
+                    ..
+                } if !self.round_over => return self.pause(),
+                Event::KeyDown {
+                    scancode: Some(scancode),
+                    ..
+                } => {
+                    if !self.round_over {
+                        if let Some((player, direction)) = self.player_direction(bindings, scancode)
+                        {
+                            let current = self.simulation.players[player].snake.head.direction;
+                            self.turns[player].push(direction, current);
+                            continue;
+                        }
+                        match bindings.action(scancode) {
+                            Some(InputAction::Pause) | Some(InputAction::Back) => {
+                                return self.pause();
+                            }
+                            _ => {}
+                        }
+                    } else if self.match_winner().is_some() {
+                        match bindings.action(scancode) {
+                            Some(InputAction::Restart) => {
+                                audio.play(Sound::Start);
+                                return self.rematch();
+                            }
+                            Some(InputAction::Back) => return Action::Pop(None),
+                            _ => {}
+                        }
+                    } else {
+                        match bindings.action(scancode) {
+                            Some(InputAction::Confirm) => self.next_round(),
+                            Some(InputAction::Back) => return Action::Pop(None),
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Action::None
+    }
+
+    fn tick(&mut self, audio: &mut Audio) -> Action {
+        if self.round_over {
+            return Action::None;
+        }
+
+        let map = &self.simulation.map;
+        let directions: Vec<_> = self
+            .simulation
+            .players
+            .iter()
+            .zip(&mut self.turns)
+            .zip(&mut self.controllers)
+            .map(|((player, turns), controller)| match controller {
+                Some(controller) => controller.next_direction(map, &player.snake),
+                None => turns.next(player.snake.head.direction),
+            })
+            .collect();
+        let turned = self
+            .simulation
+            .players
+            .iter()
+            .zip(&directions)
+            .zip(&self.controllers)
+            .any(|((player, direction), controller)| {
+                controller.is_none() && player.alive && *direction != player.snake.head.direction
+            });
+        if turned {
+            audio.play(Sound::Turn);
+        }
+        let events = self.simulation.step(&directions);
+        audio.play_events(&events);
+
+        let alive: Vec<usize> = (0..PLAYERS)
+            .filter(|i| self.simulation.players[*i].alive)
+            .collect();
+        if alive.len() <= 1 {
+            self.round_over = true;
+            self.round_winner = alive.first().cloned();
+            if let Some(winner) = self.round_winner {
+                self.wins[winner] += 1;
+            }
+        }
+        Action::None
+    }
+
+    // Both snakes move at the pace of the longer one.
+    fn tick_interval(&self) -> Duration {
+        let score = self
+            .simulation
+            .players
+            .iter()
+            .map(|player| player.score)
+            .max()
+            .unwrap_or(0);
+        self.simulation
+            .adjust_interval(self.speed.tick_interval(score))
+    }
+
+    fn music(&self) -> Option<&str> {
+        Some(audio::map_music(&self.map))
+    }
+
+    fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        font: &mut Font,
+        tiles: &Texture,
+        _logo: &Texture,
+        bindings: &Bindings,
+    ) {
+        render::draw_game(
+            canvas,
+            font,
+            tiles,
+            &self.simulation.map,
+            &self.score(),
+            Some(&self.status()),
+        );
+
+        if !self.round_over {
+            render::draw_timers(
+                canvas,
+                font,
+                tiles,
+                &self.score(),
+                &self.simulation.timers(),
+            );
+            return;
+        }
+
+        let result = match self.round_winner {
+            Some(winner) => format!("{} wins the round", self.player_name(winner)),
+            None => "Draw".to_string(),
+        };
+        let lines = match self.match_winner() {
+            Some(winner) => vec![
+                format!("{} wins the match!", self.player_name(winner)),
+                self.status(),
+                String::new(),
+                format!(
+                    "{} rematch   {} menu",
+                    bindings.key_name(InputAction::Restart),
+                    bindings.key_name(InputAction::Back)
+                ),
+            ],
+            None => vec![
+                result,
+                self.status(),
+                String::new(),
+                format!(
+                    "{} next round   {} menu",
+                    bindings.key_name(InputAction::Confirm),
+                    bindings.key_name(InputAction::Back)
+                ),
+            ],
+        };
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+        render::draw_dialog(canvas, font, &lines);
+    }
+
+    fn resume(&mut self, signal: Option<Signal>) -> Action {
+        match signal {
+            Some(Signal::ExitToMenu) => Action::Pop(None),
+            Some(Signal::Restart) => self.rematch(),
+            Some(Signal::NextLevel) | Some(Signal::MapsChanged) | None => Action::None,
+        }
+    }
+}